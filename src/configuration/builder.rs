@@ -58,6 +58,127 @@ impl ConfigurationBuilder {
         self.insert("newLineKind", value.to_string().into())
     }
 
+    /// The number of spaces for an indent. This option is ignored when using tabs.
+    /// Default: `2`
+    pub fn indent_width(&mut self, value: u8) -> &mut Self {
+        self.insert("indentWidth", (value as i32).into())
+    }
+
+    /// Whether to use tabs (true) or spaces (false) for indentation.
+    /// Default: `false`
+    pub fn use_tabs(&mut self, value: bool) -> &mut Self {
+        self.insert("useTabs", value.into())
+    }
+
+    /// The number of spaces to indent a line that continues an expression or declaration
+    /// (ex. `let x =\n    4`). Rounded to whole indents when using tabs.
+    /// Default: the value of `indent_width`
+    pub fn continuation_indent_width(&mut self, value: u8) -> &mut Self {
+        self.insert("continuationIndentWidth", (value as i32).into())
+    }
+
+    /// Spaces around binary operators. `OperatorSpacing::Grouped` drops the spaces around
+    /// arithmetic operators that bind tighter than the rest of the expression (ex. `1 + 2*3`).
+    /// Default: `OperatorSpacing::Spaced`
+    pub fn operator_spacing(&mut self, value: OperatorSpacing) -> &mut Self {
+        self.insert("operatorSpacing", value.to_string().into())
+    }
+
+    /// Whether to add spaces inside curly brackets of objects, records, blocks, variants
+    /// and patterns (`{ a = 1 }`) or not (`{a = 1}`).
+    /// Default: `true`
+    pub fn bracket_spacing(&mut self, value: bool) -> &mut Self {
+        self.insert("bracketSpacing", value.into())
+    }
+
+    /// When the last item of a comma separated list (tuples, arrays, arguments, type arguments
+    /// and parameters) is followed by a comma.
+    /// Default: `TrailingCommas::OnlyMultiLine`
+    pub fn trailing_commas(&mut self, value: TrailingCommas) -> &mut Self {
+        self.insert("trailingCommas", value.to_string().into())
+    }
+
+    /// When the last item of a block, object, record, variant or switch is followed by a semicolon.
+    /// Default: `Semicolons::OnlyMultiLine`
+    pub fn semicolons(&mut self, value: Semicolons) -> &mut Self {
+        self.insert("semicolons", value.to_string().into())
+    }
+
+    /// How `_` separators in number literals are formatted.
+    /// Default: `NumberSeparators::Off`
+    pub fn number_separators(&mut self, value: NumberSeparators) -> &mut Self {
+        self.insert("numberSeparators", value.to_string().into())
+    }
+
+    /// Group imports by `mo:base`, other `mo:` packages, `canister:` and relative paths,
+    /// and sort them by path within each group.
+    /// Default: `false`
+    pub fn sort_imports(&mut self, value: bool) -> &mut Self {
+        self.insert("sortImports", value.into())
+    }
+
+    /// Whether to re-wrap the text of line, doc and block comments to the line width.
+    /// Markdown lists, code fences, headings and blank lines between paragraphs are kept.
+    /// Only comments on their own lines and block comments with several lines are wrapped,
+    /// comments after code and single line block comments are kept as they are.
+    /// Default: `false`
+    pub fn comment_wrap(&mut self, value: bool) -> &mut Self {
+        self.insert("commentWrap", value.into())
+    }
+
+    /// Whether to write block comments as line comments (ex. `1; /* one */` as `1;  // one`).
+    /// Comments that are followed by code on the same line or contain a nested comment are kept.
+    /// Default: `false`
+    pub fn prefer_line_comments(&mut self, value: bool) -> &mut Self {
+        self.insert("preferLineComments", value.into())
+    }
+
+    /// Spaces between code and a trailing line comment. `TrailingCommentSpacing::AlignInGroup`
+    /// aligns the comments of consecutive lines in a block, object or module.
+    /// Default: `TrailingCommentSpacing::Fixed`
+    pub fn trailing_comment_spacing(&mut self, value: TrailingCommentSpacing) -> &mut Self {
+        self.insert("trailingCommentSpacing", value.to_string().into())
+    }
+
+    /// Style of doc comments, `//!` and `/** … */` are written as `///` with
+    /// `DocCommentStyle::Line` and all of them as `/** … */` with `DocCommentStyle::Block`.
+    /// Doc comments after code on the same line are kept as they are.
+    /// Default: `DocCommentStyle::Preserve`
+    pub fn doc_comment_style(&mut self, value: DocCommentStyle) -> &mut Self {
+        self.insert("docCommentStyle", value.to_string().into())
+    }
+
+    /// Whether a file with parts that could not be parsed is an error. Otherwise these parts
+    /// are kept as they are and the rest of the file is formatted.
+    /// Default: `false`
+    pub fn strict_parsing(&mut self, value: bool) -> &mut Self {
+        self.insert("strictParsing", value.into())
+    }
+
+    /// The text to use for an ignore directive (ex. `// motoko-fmt-ignore`).
+    /// Default: `"motoko-fmt-ignore"`
+    pub fn ignore_directive(&mut self, value: &str) -> &mut Self {
+        self.insert("ignoreDirective", value.to_string().into())
+    }
+
+    /// The text to use for an ignore file directive (ex. `// motoko-fmt-ignore-file`).
+    /// Default: `"motoko-fmt-ignore-file"`
+    pub fn ignore_file_directive(&mut self, value: &str) -> &mut Self {
+        self.insert("ignoreFileDirective", value.to_string().into())
+    }
+
+    /// The text to use for an ignore start directive (ex. `// motoko-fmt-ignore-start`).
+    /// Default: `"motoko-fmt-ignore-start"`
+    pub fn ignore_start_directive(&mut self, value: &str) -> &mut Self {
+        self.insert("ignoreStartDirective", value.to_string().into())
+    }
+
+    /// The text to use for an ignore end directive (ex. `// motoko-fmt-ignore-end`).
+    /// Default: `"motoko-fmt-ignore-end"`
+    pub fn ignore_end_directive(&mut self, value: &str) -> &mut Self {
+        self.insert("ignoreEndDirective", value.to_string().into())
+    }
+
     #[cfg(test)]
     pub(super) fn get_inner_config(&self) -> ConfigKeyMap {
         self.config.clone()
@@ -81,10 +202,28 @@ mod tests {
         let mut config = ConfigurationBuilder::new();
         config
             .new_line_kind(NewLineKind::CarriageReturnLineFeed)
-            .line_width(90);
+            .line_width(90)
+            .indent_width(4)
+            .use_tabs(true)
+            .continuation_indent_width(8)
+            .operator_spacing(OperatorSpacing::Grouped)
+            .bracket_spacing(false)
+            .trailing_commas(TrailingCommas::Always)
+            .semicolons(Semicolons::Never)
+            .number_separators(NumberSeparators::Add)
+            .sort_imports(true)
+            .comment_wrap(true)
+            .prefer_line_comments(true)
+            .trailing_comment_spacing(TrailingCommentSpacing::AlignInGroup)
+            .doc_comment_style(DocCommentStyle::Block)
+            .strict_parsing(true)
+            .ignore_directive("ignore")
+            .ignore_file_directive("ignore-file")
+            .ignore_start_directive("ignore-start")
+            .ignore_end_directive("ignore-end");
 
         let inner_config = config.get_inner_config();
-        assert_eq!(inner_config.len(), 2);
+        assert_eq!(inner_config.len(), 20);
         let diagnostics = resolve_config(
             inner_config,
             &resolve_global_config(ConfigKeyMap::new(), &Default::default()).config,
@@ -98,11 +237,15 @@ mod tests {
         let mut global_config = ConfigKeyMap::new();
         global_config.insert(String::from("lineWidth"), 90.into());
         global_config.insert(String::from("newLineKind"), "crlf".into());
+        global_config.insert(String::from("indentWidth"), 4.into());
         global_config.insert(String::from("useTabs"), true.into());
         let global_config = resolve_global_config(global_config, &Default::default()).config;
         let mut config_builder = ConfigurationBuilder::new();
         let config = config_builder.global_config(global_config).build();
         assert_eq!(config.line_width, 90);
+        assert_eq!(config.indent_width, 4);
+        assert_eq!(config.use_tabs, true);
+        assert_eq!(config.continuation_indent_width, 4);
         assert_eq!(
             config.new_line_kind == NewLineKind::CarriageReturnLineFeed,
             true
@@ -115,5 +258,8 @@ mod tests {
         let mut config_builder = ConfigurationBuilder::new();
         let config = config_builder.global_config(global_config).build();
         assert_eq!(config.new_line_kind == NewLineKind::LineFeed, true);
+        assert_eq!(config.indent_width, 2);
+        assert_eq!(config.use_tabs, false);
+        assert_eq!(config.continuation_indent_width, 2);
     }
 }
@@ -1,12 +1,262 @@
 use dprint_core::configuration::NewLineKind;
+use dprint_core::configuration::ParseConfigurationError;
 use serde::Deserialize;
 use serde::Serialize;
+use std::fmt;
+use std::str::FromStr;
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct Configuration {
     pub line_width: u32,
     pub new_line_kind: NewLineKind,
+    /// Number of spaces per indentation level
+    pub indent_width: u8,
+    /// Indent with tabs instead of spaces
+    pub use_tabs: bool,
+    /// Number of spaces per indentation level of a line that continues an expression
+    pub continuation_indent_width: u8,
     // Maximum number of linebreaks between
     pub max_blank_lines: u32,
+    /// Spaces around binary operators
+    pub operator_spacing: OperatorSpacing,
+    /// Spaces inside curly brackets: `{ a = 1 }`
+    pub bracket_spacing: bool,
+    /// Separator after the last item of a comma separated list
+    pub trailing_commas: TrailingCommas,
+    /// Semicolon after the last item of a block, object, record, variant or switch
+    pub semicolons: Semicolons,
+    /// Digit separators in number literals
+    pub number_separators: NumberSeparators,
+    /// Group and sort imports by their path
+    pub sort_imports: bool,
+    /// Re-wrap the text of comments to the line width
+    pub comment_wrap: bool,
+    /// Write block comments as line comments if no code follows them
+    pub prefer_line_comments: bool,
+    /// Spaces between code and a trailing line comment
+    pub trailing_comment_spacing: TrailingCommentSpacing,
+    /// Style of doc comments
+    pub doc_comment_style: DocCommentStyle,
+    /// Fail on parts that could not be parsed instead of keeping them as they are
+    pub strict_parsing: bool,
+    /// Comment text that keeps the next declaration as it is
+    pub ignore_directive: String,
+    /// Comment text at the start of a file that keeps the whole file as it is
+    pub ignore_file_directive: String,
+    /// Comment text that starts a range of declarations that are kept as they are
+    pub ignore_start_directive: String,
+    /// Comment text that ends a range started with `ignore_start_directive`
+    pub ignore_end_directive: String,
+}
+
+/// Spaces around binary operators
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum OperatorSpacing {
+    /// A space around every binary operator: `1 + 2 * 3`
+    Spaced,
+    /// No spaces around arithmetic operators that bind tighter than the rest of
+    /// the expression, if their operands are identifiers or numbers: `1 + 2*3`
+    Grouped,
+}
+
+impl FromStr for OperatorSpacing {
+    type Err = ParseConfigurationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "spaced" => Ok(OperatorSpacing::Spaced),
+            "grouped" => Ok(OperatorSpacing::Grouped),
+            _ => Err(ParseConfigurationError(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for OperatorSpacing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            OperatorSpacing::Spaced => "spaced",
+            OperatorSpacing::Grouped => "grouped",
+        })
+    }
+}
+
+/// When the last item of a comma separated list is followed by a comma
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum TrailingCommas {
+    /// Never add a trailing comma
+    Never,
+    /// Always add a trailing comma: `(a, b,)`
+    Always,
+    /// Add a trailing comma if the list is on multiple lines
+    OnlyMultiLine,
+}
+
+impl FromStr for TrailingCommas {
+    type Err = ParseConfigurationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "never" => Ok(TrailingCommas::Never),
+            "always" => Ok(TrailingCommas::Always),
+            "onlyMultiLine" => Ok(TrailingCommas::OnlyMultiLine),
+            _ => Err(ParseConfigurationError(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for TrailingCommas {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TrailingCommas::Never => "never",
+            TrailingCommas::Always => "always",
+            TrailingCommas::OnlyMultiLine => "onlyMultiLine",
+        })
+    }
+}
+
+/// When the last item of a semicolon separated list is followed by a semicolon
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum Semicolons {
+    /// Never add a semicolon after the last item: `{\n  a;\n  b\n}`
+    Never,
+    /// Always add a semicolon after the last item: `{ a; b; }`
+    Always,
+    /// Add a semicolon after the last item if the list is on multiple lines
+    OnlyMultiLine,
+}
+
+impl FromStr for Semicolons {
+    type Err = ParseConfigurationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "never" => Ok(Semicolons::Never),
+            "always" => Ok(Semicolons::Always),
+            "onlyMultiLine" => Ok(Semicolons::OnlyMultiLine),
+            _ => Err(ParseConfigurationError(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for Semicolons {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Semicolons::Never => "never",
+            Semicolons::Always => "always",
+            Semicolons::OnlyMultiLine => "onlyMultiLine",
+        })
+    }
+}
+
+/// Spaces between code and a trailing line comment
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum TrailingCommentSpacing {
+    /// Always two spaces: `a = 1;  // one`
+    Fixed,
+    /// Keep the spaces of the input, but at least two: `a = 1;    // one`
+    Preserve,
+    /// Align the comments of consecutive lines in a block, object or module
+    AlignInGroup,
+}
+
+impl FromStr for TrailingCommentSpacing {
+    type Err = ParseConfigurationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fixed" => Ok(TrailingCommentSpacing::Fixed),
+            "preserve" => Ok(TrailingCommentSpacing::Preserve),
+            "alignInGroup" => Ok(TrailingCommentSpacing::AlignInGroup),
+            _ => Err(ParseConfigurationError(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for TrailingCommentSpacing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TrailingCommentSpacing::Fixed => "fixed",
+            TrailingCommentSpacing::Preserve => "preserve",
+            TrailingCommentSpacing::AlignInGroup => "alignInGroup",
+        })
+    }
+}
+
+/// Style of doc comments
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum DocCommentStyle {
+    /// Keep `/** … */` doc comments as they are
+    Preserve,
+    /// A line comment for each line: `/// one`
+    Line,
+    /// One block comment: `/** one */`
+    Block,
+}
+
+impl FromStr for DocCommentStyle {
+    type Err = ParseConfigurationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "preserve" => Ok(DocCommentStyle::Preserve),
+            "line" => Ok(DocCommentStyle::Line),
+            "block" => Ok(DocCommentStyle::Block),
+            _ => Err(ParseConfigurationError(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for DocCommentStyle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DocCommentStyle::Preserve => "preserve",
+            DocCommentStyle::Line => "line",
+            DocCommentStyle::Block => "block",
+        })
+    }
+}
+
+/// How `_` separators in number literals are formatted
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum NumberSeparators {
+    /// Keep number literals as they are
+    Off,
+    /// Group digits by three, hexadecimal digits by four: `1_000_000`, `0x0100_0000`
+    Add,
+    /// Remove all separators: `1000000`
+    Remove,
+    /// Keep separators, but lowercase exponent markers
+    Preserve,
+}
+
+impl FromStr for NumberSeparators {
+    type Err = ParseConfigurationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(NumberSeparators::Off),
+            "add" => Ok(NumberSeparators::Add),
+            "remove" => Ok(NumberSeparators::Remove),
+            "preserve" => Ok(NumberSeparators::Preserve),
+            _ => Err(ParseConfigurationError(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for NumberSeparators {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            NumberSeparators::Off => "off",
+            NumberSeparators::Add => "add",
+            NumberSeparators::Remove => "remove",
+            NumberSeparators::Preserve => "preserve",
+        })
+    }
 }
@@ -1,4 +1,7 @@
-use super::Configuration;
+use super::{
+    Configuration, DocCommentStyle, NumberSeparators, OperatorSpacing, Semicolons, TrailingCommas,
+    TrailingCommentSpacing,
+};
 use dprint_core::configuration::*;
 
 /// Resolves configuration from a collection of key value strings.
@@ -30,6 +33,12 @@ pub fn resolve_config(
     let mut diagnostics = Vec::new();
     let mut config = config;
 
+    let indent_width = get_value(
+        &mut config,
+        "indentWidth",
+        global_config.indent_width.unwrap_or(2),
+        &mut diagnostics,
+    );
     let resolved_config = Configuration {
         line_width: get_value(
             &mut config,
@@ -45,7 +54,85 @@ pub fn resolve_config(
                 .unwrap_or(RECOMMENDED_GLOBAL_CONFIGURATION.new_line_kind),
             &mut diagnostics,
         ),
+        indent_width,
+        use_tabs: get_value(
+            &mut config,
+            "useTabs",
+            global_config.use_tabs.unwrap_or(false),
+            &mut diagnostics,
+        ),
+        continuation_indent_width: get_value(
+            &mut config,
+            "continuationIndentWidth",
+            indent_width,
+            &mut diagnostics,
+        ),
         max_blank_lines: get_value(&mut config, "maxBlankLines", 2, &mut diagnostics),
+        operator_spacing: get_value(
+            &mut config,
+            "operatorSpacing",
+            OperatorSpacing::Spaced,
+            &mut diagnostics,
+        ),
+        bracket_spacing: get_value(&mut config, "bracketSpacing", true, &mut diagnostics),
+        trailing_commas: get_value(
+            &mut config,
+            "trailingCommas",
+            TrailingCommas::OnlyMultiLine,
+            &mut diagnostics,
+        ),
+        semicolons: get_value(
+            &mut config,
+            "semicolons",
+            Semicolons::OnlyMultiLine,
+            &mut diagnostics,
+        ),
+        number_separators: get_value(
+            &mut config,
+            "numberSeparators",
+            NumberSeparators::Off,
+            &mut diagnostics,
+        ),
+        sort_imports: get_value(&mut config, "sortImports", false, &mut diagnostics),
+        comment_wrap: get_value(&mut config, "commentWrap", false, &mut diagnostics),
+        prefer_line_comments: get_value(&mut config, "preferLineComments", false, &mut diagnostics),
+        trailing_comment_spacing: get_value(
+            &mut config,
+            "trailingCommentSpacing",
+            TrailingCommentSpacing::Fixed,
+            &mut diagnostics,
+        ),
+        doc_comment_style: get_value(
+            &mut config,
+            "docCommentStyle",
+            DocCommentStyle::Preserve,
+            &mut diagnostics,
+        ),
+        strict_parsing: get_value(&mut config, "strictParsing", false, &mut diagnostics),
+        ignore_directive: get_value(
+            &mut config,
+            "ignoreDirective",
+            "motoko-fmt-ignore".to_string(),
+            &mut diagnostics,
+        ),
+        ignore_file_directive: get_value(
+            &mut config,
+            "ignoreFileDirective",
+            "motoko-fmt-ignore-file".to_string(),
+            &mut diagnostics,
+        ),
+        ignore_start_directive: get_value(
+            &mut config,
+            "ignoreStartDirective",
+            "motoko-fmt-ignore-start".to_string(),
+            &mut diagnostics,
+        ),
+        ignore_end_directive: get_value(
+            &mut config,
+            "ignoreEndDirective",
+            "motoko-fmt-ignore-end".to_string(),
+            &mut diagnostics,
+        ),
     };
 
     diagnostics.extend(get_unknown_property_diagnostics(config));
@@ -0,0 +1,415 @@
+use std::ops::Range;
+use std::path::Path;
+
+use crate::configuration::Configuration;
+use crate::format_text::{format_text_with_diagnostics, has_ignore_file_directive};
+use crate::generation::{has_directive, is_comment, is_list_node};
+use crate::motoko_parser as motoko;
+use crate::motoko_parser::{Attachment, Diagnostic, Node, NodeType};
+
+/// Replacement of a part of the original text
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TextEdit {
+    /// Byte range in the original text
+    pub range: Range<usize>,
+    pub new_text: String,
+}
+
+/// Format only the declarations touching `range` (byte offsets into `text`)
+///
+/// The whole file is formatted and the smallest declarations enclosing the range are replaced
+/// by their formatted text, so they look the same as after formatting the whole file.
+/// Everything outside of the returned edit stays as it is.
+/// Returns `None` if there is nothing to format or the declarations are already formatted.
+pub fn format_range(
+    file_path: &Path,
+    text: &str,
+    range: Range<usize>,
+    config: &Configuration,
+) -> anyhow::Result<Option<TextEdit>> {
+    if has_ignore_file_directive(text, &config.ignore_file_directive) {
+        return Ok(None);
+    }
+    let nodes =
+        motoko::parse(text).map_err(|err| Diagnostic::from_pest(Some(file_path), text, &err))?;
+
+    let mut candidates = vec![];
+    collect_enclosing(&nodes, text, &range, &mut candidates);
+    if candidates.is_empty() {
+        return Ok(None);
+    }
+    let Some(formatted) = format_text_with_diagnostics(file_path, text, config)?.text else {
+        return Ok(None);
+    };
+    let Ok(formatted_nodes) = motoko::parse(&formatted) else {
+        return Ok(None);
+    };
+
+    // try the innermost declarations first, fall back to the enclosing ones
+    // if they can't be found in the formatted text
+    for declarations in candidates.iter().rev() {
+        // declarations after an ignore directive are kept as they are
+        if declarations
+            .iter()
+            .any(|n| is_ignored(&nodes, n, config, &mut IgnoreState::default()) == Some(true))
+        {
+            return Ok(None);
+        }
+        if let Some(edit) =
+            edit_declarations(&nodes, text, &formatted_nodes, &formatted, declarations)
+        {
+            return Ok(edit);
+        }
+    }
+    Ok(None)
+}
+
+/// Collect the declarations touching `range`, from the outermost to the innermost level
+fn collect_enclosing<'a>(
+    nodes: &'a [Node],
+    text: &str,
+    range: &Range<usize>,
+    candidates: &mut Vec<Vec<&'a Node>>,
+) {
+    let mut declarations = vec![];
+    find_declarations(nodes, &mut declarations);
+    let touching: Vec<&Node> = declarations
+        .into_iter()
+        .filter(|n| touches(n, text, range))
+        .collect();
+    if touching.is_empty() {
+        return;
+    }
+    candidates.push(touching.clone());
+    if let [single] = touching[..] {
+        if single.start <= range.start && range.end <= trimmed_end(single, text) {
+            collect_enclosing(&single.children, text, range, candidates);
+        }
+    }
+}
+
+/// Declarations that are not nested inside another declaration
+fn find_declarations<'a>(nodes: &'a [Node], declarations: &mut Vec<&'a Node>) {
+    for n in nodes {
+        match n.node_type {
+            NodeType::Declaration | NodeType::Import => declarations.push(n),
+            _ => find_declarations(&n.children, declarations),
+        }
+    }
+}
+
+/// Ignore directives that apply to the next node, see `gen_list_body`
+#[derive(Default)]
+struct IgnoreState {
+    next: bool,
+    range: bool,
+}
+
+/// Whether `target` or a node around it is after an ignore directive or in an ignore range.
+/// Returns `None` if `target` is not in `nodes`.
+fn is_ignored(
+    nodes: &[Node],
+    target: &Node,
+    config: &Configuration,
+    state: &mut IgnoreState,
+) -> Option<bool> {
+    for n in nodes {
+        if n.attachment == Some(Attachment::Trailing) {
+            continue;
+        }
+        if is_comment(n) || n.node_type == NodeType::Header {
+            if has_directive(n, &config.ignore_end_directive) {
+                state.range = false;
+            } else if has_directive(n, &config.ignore_start_directive) {
+                state.range = true;
+            } else if has_directive(n, &config.ignore_directive) {
+                state.next = true;
+            }
+            continue;
+        }
+        if n.start == n.end
+            || matches!(
+                n.node_type,
+                NodeType::WHITESPACE | NodeType::Semicolon | NodeType::EOI
+            )
+        {
+            continue;
+        }
+        // an ignore range continues from the imports into the declarations
+        if is_list_node(n) || n.node_type == NodeType::Program {
+            match is_ignored(&n.children, target, config, state) {
+                Some(ignored) => return Some(ignored),
+                None => continue,
+            }
+        }
+        if !(n.start <= target.start && target.end <= n.end) {
+            state.next = false;
+            continue;
+        }
+        if state.next || state.range {
+            return Some(true);
+        }
+        if std::ptr::eq(n, target) {
+            return Some(false);
+        }
+        // directives inside of a node only apply to its own lists
+        return Some(
+            is_ignored(&n.children, target, config, &mut IgnoreState::default()).unwrap_or(false),
+        );
+    }
+    None
+}
+
+fn touches(node: &Node, text: &str, range: &Range<usize>) -> bool {
+    let end = end_with_separator(node, text);
+    if range.is_empty() {
+        node.start <= range.start && range.start <= end
+    } else {
+        node.start < range.end && range.start < end
+    }
+}
+
+/// End of the node without trailing whitespace
+fn trimmed_end(node: &Node, text: &str) -> usize {
+    node.start + text[node.start..node.end].trim_end().len()
+}
+
+/// End of the node including a following `;`
+fn end_with_separator(node: &Node, text: &str) -> usize {
+    let end = trimmed_end(node, text);
+    let rest = &text[end..];
+    let spaces = rest.len() - rest.trim_start_matches([' ', '\t']).len();
+    if rest[spaces..].starts_with(';') {
+        end + spaces + 1
+    } else {
+        end
+    }
+}
+
+/// Replace the declarations by the same declarations of the formatted file
+fn edit_declarations(
+    nodes: &[Node],
+    text: &str,
+    formatted_nodes: &[Node],
+    formatted: &str,
+    declarations: &[&Node],
+) -> Option<Option<TextEdit>> {
+    let first = declarations.first()?;
+    let last = declarations.last()?;
+    let formatted_first = declaration_at(formatted_nodes, &declaration_path(nodes, first)?)?;
+    let formatted_last = declaration_at(formatted_nodes, &declaration_path(nodes, last)?)?;
+    // e.g. sorted imports are in another order
+    if !same_code(first, formatted_first) || !same_code(last, formatted_last) {
+        return None;
+    }
+
+    // include the separator, it is added by the formatter
+    let range = first.start..end_with_separator(last, text);
+    let new_text = &formatted[formatted_first.start..end_with_separator(formatted_last, formatted)];
+    Some(if new_text == &text[range.clone()] {
+        None
+    } else {
+        Some(TextEdit {
+            range,
+            new_text: new_text.to_string(),
+        })
+    })
+}
+
+/// Indices of the declarations from the outermost one to `target`, see `collect_enclosing`
+fn declaration_path(nodes: &[Node], target: &Node) -> Option<Vec<usize>> {
+    let mut declarations = vec![];
+    find_declarations(nodes, &mut declarations);
+    let (i, declaration) = declarations
+        .into_iter()
+        .enumerate()
+        .find(|(_, n)| n.start <= target.start && target.end <= n.end)?;
+    if std::ptr::eq(declaration, target) {
+        return Some(vec![i]);
+    }
+    let mut path = declaration_path(&declaration.children, target)?;
+    path.insert(0, i);
+    Some(path)
+}
+
+fn declaration_at<'a>(nodes: &'a [Node], path: &[usize]) -> Option<&'a Node> {
+    let mut declarations = vec![];
+    find_declarations(nodes, &mut declarations);
+    let declaration = *declarations.get(*path.first()?)?;
+    match &path[1..] {
+        [] => Some(declaration),
+        rest => declaration_at(&declaration.children, rest),
+    }
+}
+
+/// Whether the nodes have the same identifiers, keywords and literals, ignoring the case and
+/// digit separators of numbers
+fn same_code(a: &Node, b: &Node) -> bool {
+    let code = |n: &Node| -> String {
+        n.original
+            .chars()
+            .filter(|c| c.is_alphanumeric())
+            .flat_map(char::to_lowercase)
+            .collect()
+    };
+    code(a) == code(b)
+}
+
+#[cfg(test)]
+mod test_format_range {
+    use super::*;
+    use crate::configuration::ConfigurationBuilder;
+
+    fn apply(text: &str, range: Range<usize>) -> String {
+        let config = ConfigurationBuilder::new().build();
+        match format_range(Path::new("file.mo"), text, range, &config).unwrap() {
+            Some(edit) => {
+                let mut result = text.to_string();
+                result.replace_range(edit.range, &edit.new_text);
+                result
+            }
+            None => text.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_format_single_declaration() {
+        let text = "let  a  =  1;\nlet  b  =  2;\nlet  c  =  3;\n";
+        assert_eq!(
+            apply(text, 16..17),
+            "let  a  =  1;\nlet b = 2;\nlet  c  =  3;\n"
+        );
+        // cursor at the end of a declaration
+        assert_eq!(
+            apply(text, 13..13),
+            "let a = 1;\nlet  b  =  2;\nlet  c  =  3;\n"
+        );
+    }
+
+    #[test]
+    fn test_format_multiple_declarations() {
+        let text = "let  a  =  1;\nlet  b  =  2;\nlet  c  =  3;\n";
+        assert_eq!(
+            apply(text, 5..20),
+            "let a = 1;\nlet b = 2;\nlet  c  =  3;\n"
+        );
+    }
+
+    #[test]
+    fn test_format_nested_declaration() {
+        let text = "module {\n  public func f() {\n    let  x  =  1;\n    x\n  };\n};\n";
+        let start = text.find("x  =").unwrap();
+        assert_eq!(
+            apply(text, start..start + 1),
+            "module {\n  public func f() {\n    let x = 1;\n    x\n  };\n};\n"
+        );
+
+        let text = "module {\n  public func f() {\n let  x  =  1;\n    x }\n};\n";
+        let start = text.find("f()").unwrap();
+        assert_eq!(
+            apply(text, start..start + 1),
+            "module {\n  public func f() {\n    let x = 1;\n    x;\n  };\n};\n"
+        );
+    }
+
+    fn format_all(text: &str) -> String {
+        let config = ConfigurationBuilder::new().build();
+        crate::format_text(Path::new("file.mo"), text, &config)
+            .unwrap()
+            .unwrap_or_else(|| text.to_string())
+    }
+
+    #[test]
+    fn test_same_as_whole_file() {
+        // the last item of a block gets its separator
+        let text =
+            "module {\n  public func f(y : Nat) : Nat {\n    let  z = 1;\n    y  +  z\n  };\n};\n";
+        let start = text.find("y  +").unwrap();
+        let result = apply(text, start..start + 1);
+        assert!(result.contains("    y + z;\n"));
+        assert_eq!(apply(&result, 0..text.len()), format_all(text));
+
+        // the separator goes before a trailing comment
+        let text = "let a = 1;\nlet  b  =  2  // c\n";
+        let start = text.find("b  =").unwrap();
+        assert_eq!(
+            apply(text, start..start + 1),
+            "let a = 1;\nlet b = 2;  // c\n"
+        );
+    }
+
+    #[test]
+    fn test_block_comment_is_idempotent() {
+        let text = concat!(
+            "module {\n",
+            "  public func f() {\n",
+            "    /* one\n",
+            "       two */\n",
+            "    let  x  =  1;\n",
+            "  };\n",
+            "};\n",
+        );
+        let start = text.find("f()").unwrap();
+        let once = apply(text, start..start + 1);
+        assert_eq!(once, format_all(text));
+        let start = once.find("f()").unwrap();
+        assert_eq!(apply(&once, start..start + 1), once);
+    }
+
+    #[test]
+    fn test_ignored_declaration() {
+        let text = "let  a  =  1;\n// motoko-fmt-ignore\nlet  b  =  2;\nlet  c  =  3;\n";
+        let start = text.find("b  =").unwrap();
+        assert_eq!(apply(text, start..start + 1), text);
+        let start = text.find("c  =").unwrap();
+        assert_eq!(
+            apply(text, start..start + 1),
+            "let  a  =  1;\n// motoko-fmt-ignore\nlet  b  =  2;\nlet c = 3;\n"
+        );
+
+        // the declaration around the range is ignored
+        let text =
+            "module {\n  // motoko-fmt-ignore\n  public func f() {\n    let  x  =  1;\n  };\n};\n";
+        let start = text.find("x  =").unwrap();
+        assert_eq!(apply(text, start..start + 1), text);
+
+        // a directive after code on the same line doesn't apply
+        let text = "let  a  =  1;  // motoko-fmt-ignore\nlet  b  =  2;\n";
+        let start = text.find("b  =").unwrap();
+        assert_eq!(
+            apply(text, start..start + 1),
+            "let  a  =  1;  // motoko-fmt-ignore\nlet b = 2;\n"
+        );
+    }
+
+    #[test]
+    fn test_ignore_range() {
+        let text = concat!(
+            "// motoko-fmt-ignore-start\n",
+            "let  a  =  1;\n",
+            "// motoko-fmt-ignore-end\n",
+            "let  b  =  2;\n",
+        );
+        let start = text.find("a  =").unwrap();
+        assert_eq!(apply(text, start..start + 1), text);
+        let start = text.find("b  =").unwrap();
+        assert_eq!(
+            apply(text, start..start + 1),
+            text.replace("let  b  =  2;", "let b = 2;")
+        );
+
+        let text = "import A \"a\";\n// motoko-fmt-ignore-start\nimport B \"b\";\nlet  a  =  1;\n";
+        let start = text.find("a  =").unwrap();
+        assert_eq!(apply(text, start..start + 1), text);
+    }
+
+    #[test]
+    fn test_nothing_to_format() {
+        let config = ConfigurationBuilder::new().build();
+        let text = "let a = 1;\n\nlet  b  =  2;\n";
+        let edit = format_range(Path::new("file.mo"), text, 0..3, &config).unwrap();
+        assert_eq!(edit, None);
+        let edit = format_range(Path::new("file.mo"), text, 11..11, &config).unwrap();
+        assert_eq!(edit, None);
+    }
+}
@@ -4,12 +4,49 @@ use dprint_core::plugins::FormatResult;
 use std::path::Path;
 
 use crate::motoko_parser as motoko;
+use crate::motoko_parser::Diagnostic;
 
 use crate::configuration::Configuration;
-use crate::generation::generate;
+use crate::generation::{generate, Indentation};
 
-pub fn format_text(_file_path: &Path, text: &str, config: &Configuration) -> FormatResult {
-    let nodes = motoko::parse(text)?;
+/// Result of formatting a file that might contain parts which could not be parsed
+#[derive(Debug)]
+pub struct FormatTextResult {
+    /// Formatted text or `None` if the text did not change
+    pub text: Option<String>,
+    /// Errors for all parts that were kept unformatted
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Format a file, parts that could not be parsed are kept as they are,
+/// or are an error with `strict_parsing`
+pub fn format_text(file_path: &Path, text: &str, config: &Configuration) -> FormatResult {
+    let result = format_text_with_diagnostics(file_path, text, config)?;
+    if config.strict_parsing && !result.diagnostics.is_empty() {
+        let messages: Vec<String> = result.diagnostics.iter().map(|d| d.to_string()).collect();
+        anyhow::bail!(messages.join("\n\n"));
+    }
+    Ok(result.text)
+}
+
+pub fn format_text_with_diagnostics(
+    file_path: &Path,
+    text: &str,
+    config: &Configuration,
+) -> anyhow::Result<FormatTextResult> {
+    if has_ignore_file_directive(text, &config.ignore_file_directive) {
+        return Ok(FormatTextResult {
+            text: None,
+            diagnostics: vec![],
+        });
+    }
+
+    let nodes =
+        motoko::parse(text).map_err(|err| Diagnostic::from_pest(Some(file_path), text, &err))?;
+    let diagnostics = motoko::invalid_part_errors(text, &nodes)
+        .iter()
+        .map(|err| Diagnostic::from_pest(Some(file_path), text, err))
+        .collect();
 
     let result = dprint_core::formatting::format(
         // generate must be called inside the closure,
@@ -23,10 +60,35 @@ pub fn format_text(_file_path: &Path, text: &str, config: &Configuration) -> For
         result,
         nodes.iter().map(|n| format!("{:?}", n)).collect::<String>()
     );
-    if result == text {
-        Ok(None)
-    } else {
-        Ok(Some(result))
+    Ok(FormatTextResult {
+        text: if result == text { None } else { Some(result) },
+        diagnostics,
+    })
+}
+
+/// Whether one of the comments at the start of the file is the ignore file directive
+pub(crate) fn has_ignore_file_directive(text: &str, directive: &str) -> bool {
+    let mut rest = text.trim_start();
+    loop {
+        let comment = if let Some(line) = rest.strip_prefix("//") {
+            let (comment, next) = line.split_at(line.find('\n').unwrap_or(line.len()));
+            rest = next;
+            comment
+        } else if let Some(block) = rest.strip_prefix("/*") {
+            match block.find("*/") {
+                Some(end) => {
+                    rest = &block[end + 2..];
+                    &block[..end]
+                }
+                None => return false,
+            }
+        } else {
+            return false;
+        };
+        if comment.trim() == directive {
+            return true;
+        }
+        rest = rest.trim_start();
     }
 }
 
@@ -44,9 +106,44 @@ pub fn trace_file(
 
 fn config_to_print_options(text: &str, config: &Configuration) -> PrintOptions {
     PrintOptions {
-        indent_width: 2,
+        indent_width: Indentation::from_config(config).unit,
         max_width: config.line_width,
-        use_tabs: false,
+        use_tabs: config.use_tabs,
         new_line_text: resolve_new_line_kind(text, config.new_line_kind),
     }
 }
+
+#[cfg(test)]
+mod test_format_text {
+    use super::*;
+    use crate::configuration::ConfigurationBuilder;
+
+    const INVALID: &str = "let   a=1;\nlet b = = 2;\n";
+
+    #[test]
+    fn test_invalid_part() {
+        let config = ConfigurationBuilder::new().build();
+        let path = Path::new("file.mo");
+
+        let result = format_text_with_diagnostics(path, INVALID, &config).unwrap();
+        assert_eq!(result.text.as_deref(), Some("let a = 1;\nlet b = = 2;\n"));
+        assert_eq!(result.diagnostics.len(), 1);
+        assert_eq!(format_text(path, INVALID, &config).unwrap(), result.text);
+    }
+
+    #[test]
+    fn test_strict_parsing() {
+        let config = ConfigurationBuilder::new().strict_parsing(true).build();
+        let path = Path::new("file.mo");
+
+        let result = format_text_with_diagnostics(path, INVALID, &config).unwrap();
+        let err = format_text(path, INVALID, &config).unwrap_err();
+        assert_eq!(err.to_string(), result.diagnostics[0].to_string());
+        assert_eq!(
+            format_text(path, "let   a=1;\n", &config)
+                .unwrap()
+                .as_deref(),
+            Some("let a = 1;\n")
+        );
+    }
+}
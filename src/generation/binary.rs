@@ -0,0 +1,235 @@
+use crate::motoko_parser::{Node, NodeType};
+
+/// Precedence of `+`, `-` and `#`, operators above it are arithmetic or bitwise
+pub const ADDITIVE: u8 = 5;
+
+/// Precedence of the shift and rotate operators, they need a space before them: `x >> 2`
+pub const SHIFT: u8 = 10;
+
+/// Precedence of a binary operator, higher binds tighter
+pub fn precedence(op: &str) -> Option<u8> {
+    Some(match op {
+        "|>" => 1,
+        "or" => 2,
+        "and" => 3,
+        "==" | "!=" | "<" | "<=" | ">" | ">=" => 4,
+        "+" | "-" | "+%" | "-%" | "#" => ADDITIVE,
+        "*" | "/" | "%" | "*%" => 6,
+        "|" => 7,
+        "&" => 8,
+        "^" => 9,
+        "<<" | ">>" | "<<>" | "<>>" => SHIFT,
+        "**" | "**%" => 11,
+        _ => return None,
+    })
+}
+
+/// Operands and operators of nested `ExpBin` and `ExpBinContinue` nodes
+pub fn flatten_exp_bin(node: &Node) -> Vec<&Node> {
+    let mut nodes = vec![];
+    for child in node.children.iter() {
+        match child.node_type {
+            NodeType::ExpBin | NodeType::ExpBinContinue => nodes.extend(flatten_exp_bin(child)),
+            _ => nodes.push(child),
+        }
+    }
+    nodes
+}
+
+/// Join alternating operands and operators with their precedence into a tree.
+/// All operators are left associative, so split at the last one with the lowest precedence.
+pub fn group_by_precedence<T, O>(
+    mut operands: Vec<T>,
+    mut ops: Vec<(O, u8)>,
+    join: &impl Fn(T, O, u8, T) -> T,
+) -> T {
+    let lowest = match ops.iter().map(|(_, p)| *p).min() {
+        Some(lowest) => lowest,
+        None => return operands.remove(0),
+    };
+    let i = ops.iter().rposition(|(_, p)| *p == lowest).unwrap();
+    let rhs_operands = operands.split_off(i + 1);
+    let rhs_ops = ops.split_off(i + 1);
+    let (op, precedence) = ops.pop().unwrap();
+    let lhs = group_by_precedence(operands, ops, join);
+    let rhs = group_by_precedence(rhs_operands, rhs_ops, join);
+    join(lhs, op, precedence, rhs)
+}
+
+/// Binary expression with its operators grouped by precedence
+#[derive(Debug)]
+pub enum BinaryExp<'a> {
+    Operand(&'a Node),
+    Op {
+        lhs: Box<BinaryExp<'a>>,
+        op: &'a Node,
+        precedence: u8,
+        rhs: Box<BinaryExp<'a>>,
+    },
+}
+
+impl<'a> BinaryExp<'a> {
+    /// Group alternating operands and operators, e.g. the flattened children of an `ExpBin`.
+    /// Returns `None` for anything else, e.g. comments or type annotations.
+    pub fn from_nodes(nodes: &[&'a Node]) -> Option<Self> {
+        let nodes: Vec<&Node> = nodes
+            .iter()
+            .copied()
+            .filter(|n| n.node_type != NodeType::WHITESPACE)
+            .collect();
+        if nodes.len() < 3 || nodes.len().is_multiple_of(2) {
+            return None;
+        }
+        let mut operands = vec![];
+        let mut ops = vec![];
+        for (i, n) in nodes.iter().enumerate() {
+            if i % 2 == 0 {
+                if n.node_type != NodeType::ExpUn {
+                    return None;
+                }
+                operands.push(BinaryExp::Operand(n));
+            } else {
+                if !matches!(
+                    n.node_type,
+                    NodeType::BinOp | NodeType::RelOp | NodeType::KeywordAnd | NodeType::KeywordOr
+                ) {
+                    return None;
+                }
+                ops.push((*n, precedence(n.original.trim())?));
+            }
+        }
+        Some(Self::group(operands, ops))
+    }
+
+    fn group(operands: Vec<BinaryExp<'a>>, ops: Vec<(&'a Node, u8)>) -> Self {
+        group_by_precedence(operands, ops, &|lhs, op, precedence, rhs| BinaryExp::Op {
+            lhs: Box::new(lhs),
+            op,
+            precedence,
+            rhs: Box::new(rhs),
+        })
+    }
+
+    pub fn precedence(&self) -> Option<u8> {
+        match self {
+            BinaryExp::Operand(_) => None,
+            BinaryExp::Op { precedence, .. } => Some(*precedence),
+        }
+    }
+
+    /// Width on a single line with a space around each operator
+    pub fn width(&self) -> usize {
+        match self {
+            BinaryExp::Operand(n) => n
+                .original
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ")
+                .chars()
+                .count(),
+            BinaryExp::Op { lhs, op, rhs, .. } => {
+                lhs.width() + op.original.trim().chars().count() + rhs.width() + 2
+            }
+        }
+    }
+
+    /// Leftmost operand
+    pub fn first(&self) -> &'a Node {
+        match self {
+            BinaryExp::Operand(n) => n,
+            BinaryExp::Op { lhs, .. } => lhs.first(),
+        }
+    }
+
+    /// Rightmost operand
+    pub fn last(&self) -> &'a Node {
+        match self {
+            BinaryExp::Operand(n) => n,
+            BinaryExp::Op { rhs, .. } => rhs.last(),
+        }
+    }
+}
+
+/// Identifier or number literal that reads well without spaces around an operator: `2*x`
+pub fn is_simple_operand(node: &Node) -> bool {
+    match node.node_type {
+        NodeType::Id => true,
+        NodeType::Lit => node.original.starts_with(|c: char| c.is_ascii_digit()),
+        _ => {
+            let mut children = node
+                .children
+                .iter()
+                .filter(|n| n.node_type != NodeType::WHITESPACE);
+            match (children.next(), children.next()) {
+                (Some(child), None) => is_simple_operand(child),
+                _ => false,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_binary {
+    use super::*;
+    use crate::motoko_parser::parse;
+
+    fn find_exp_bin(nodes: &[Node]) -> Option<&Node> {
+        for n in nodes {
+            if n.node_type == NodeType::ExpBin && n.has_child(&NodeType::ExpBinContinue) {
+                return Some(n);
+            }
+            if let Some(n) = find_exp_bin(&n.children) {
+                return Some(n);
+            }
+        }
+        None
+    }
+
+    fn grouped(exp: &BinaryExp) -> String {
+        match exp {
+            BinaryExp::Operand(n) => n.original.trim().to_string(),
+            BinaryExp::Op { lhs, op, rhs, .. } => {
+                format!("({} {} {})", grouped(lhs), op.original.trim(), grouped(rhs))
+            }
+        }
+    }
+
+    fn group(text: &str) -> Option<String> {
+        let nodes = parse(text).unwrap();
+        let node = find_exp_bin(&nodes).unwrap();
+        BinaryExp::from_nodes(&flatten_exp_bin(node)).map(|exp| grouped(&exp))
+    }
+
+    #[test]
+    fn test_precedence() {
+        assert_eq!(
+            group("1 + 2 * 3 - 4 / 2").unwrap(),
+            "((1 + (2 * 3)) - (4 / 2))"
+        );
+        assert_eq!(
+            group("a or b and c == d + 1").unwrap(),
+            "(a or (b and (c == (d + 1))))"
+        );
+        assert_eq!(group("2 ** 3 * 4").unwrap(), "((2 ** 3) * 4)");
+        assert_eq!(group("x |> f _ |> g _").unwrap(), "((x |> f _) |> g _)");
+        assert_eq!(group("a - b - c").unwrap(), "((a - b) - c)");
+    }
+
+    #[test]
+    fn test_not_grouped() {
+        assert_eq!(group("a : Nat == b"), None);
+        assert_eq!(group("a + // one\n b"), None);
+    }
+
+    #[test]
+    fn test_simple_operand() {
+        let nodes = parse("x + 1_000 + f(x) + a.b").unwrap();
+        let node = find_exp_bin(&nodes).unwrap();
+        let operands: Vec<bool> = flatten_exp_bin(node)
+            .into_iter()
+            .filter(|n| n.node_type == NodeType::ExpUn)
+            .map(is_simple_operand)
+            .collect();
+        assert_eq!(operands, vec![true, true, false, false]);
+    }
+}
@@ -0,0 +1,385 @@
+use super::helper::gen_spaces;
+use dprint_core::formatting::*;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Part of the text of a comment for re-wrapping
+#[derive(Debug, PartialEq, Eq)]
+pub enum Block {
+    /// A line that is kept as it is, e.g. a blank line, heading, table row or code
+    Line(String),
+    /// Words of consecutive lines that are re-wrapped, continuation lines start with `hang`
+    Paragraph {
+        indent: String,
+        hang: String,
+        words: Vec<String>,
+    },
+}
+
+/// Split the lines of a comment into paragraphs and lines that must not be re-wrapped.
+/// Markdown list items start a new paragraph, code fences are kept with their content.
+/// The indentation of the first line is ignored for the base indentation of the comment,
+/// if it follows the opening `/*` of a block comment.
+pub fn split_blocks(lines: &[&str], skip_first_indent: bool) -> Vec<Block> {
+    let lines: Vec<&str> = lines.iter().map(|l| l.trim_end()).collect();
+    let base = lines
+        .iter()
+        .skip(if skip_first_indent && lines.len() > 1 {
+            1
+        } else {
+            0
+        })
+        .filter(|l| !l.is_empty())
+        .map(|l| indent_of(l).len())
+        .min()
+        .unwrap_or(0);
+
+    let mut blocks = vec![];
+    let mut fence: Option<&str> = None;
+    // lines of the last paragraph, it is closed by anything that isn't plain text
+    let mut paragraph_lines = 0;
+    for line in lines {
+        let indent = indent_of(line);
+        let text = &line[indent.len()..];
+        if let Some(f) = fence {
+            if text.starts_with(f) {
+                fence = None;
+            }
+            blocks.push(Block::Line(line.to_string()));
+            paragraph_lines = 0;
+            continue;
+        }
+        if text.starts_with("```") || text.starts_with("~~~") {
+            fence = Some(&text[..3]);
+        }
+        if fence.is_some() || text.is_empty() || indent.len() >= base + 4 || is_markdown_line(text)
+        {
+            blocks.push(Block::Line(line.to_string()));
+            paragraph_lines = 0;
+            continue;
+        }
+
+        let words = text.split_whitespace().map(|w| w.to_string());
+        match (blocks.last_mut(), list_marker(text)) {
+            (Some(Block::Paragraph { hang, words: w, .. }), None) if paragraph_lines > 0 => {
+                if paragraph_lines == 1 && !is_list_item(w) {
+                    *hang = indent.to_string();
+                }
+                w.extend(words);
+                paragraph_lines += 1;
+            }
+            (_, marker) => {
+                let hang = match marker {
+                    Some(len) => format!("{}{}", indent, " ".repeat(len + 1)),
+                    None if indent.len() < base => " ".repeat(base),
+                    None => indent.to_string(),
+                };
+                blocks.push(Block::Paragraph {
+                    indent: indent.to_string(),
+                    hang,
+                    words: words.collect(),
+                });
+                paragraph_lines = 1;
+            }
+        }
+    }
+    blocks
+}
+
+/// Lines of the text of a block comment without the indentation they have in common.
+/// The first line follows the opening `/*`, blank lines at the start and end are removed.
+pub fn block_lines(content: &str) -> Vec<String> {
+    let mut lines = content.lines().map(str::trim_end);
+    let first = lines.next().unwrap_or_default().trim_start();
+    let lines: Vec<&str> = lines.collect();
+    let base = lines
+        .iter()
+        .filter(|l| !l.is_empty())
+        .map(|l| indent_of(l).len())
+        .min()
+        .unwrap_or(0);
+    let mut result: Vec<String> = std::iter::once(first)
+        .chain(
+            lines
+                .iter()
+                .map(|l| l.get(base..).unwrap_or(l.trim_start())),
+        )
+        .map(|l| l.to_string())
+        .collect();
+
+    while result.last().is_some_and(|l| l.is_empty()) {
+        result.pop();
+    }
+    let leading_blank_lines = result.iter().take_while(|l| l.is_empty()).count();
+    result.drain(..leading_blank_lines);
+    result
+}
+
+/// Lines of a doc comment (`/** … */`) without the `*` at the start of each line.
+/// Returns `None` for other block comments.
+pub fn doc_block_lines(comment: &str) -> Option<Vec<String>> {
+    let text = comment.strip_prefix("/**")?.strip_suffix("*/")?;
+    if text.starts_with('*') || text.contains("/*") {
+        return None;
+    }
+    let has_gutter = text
+        .lines()
+        .skip(1)
+        .filter(|l| !l.trim().is_empty())
+        .all(|l| l.trim_start().starts_with('*'));
+    if !has_gutter {
+        return Some(block_lines(text));
+    }
+    let text: Vec<&str> = text
+        .lines()
+        .enumerate()
+        .map(|(i, l)| match l.trim_start().strip_prefix('*') {
+            Some(rest) if i > 0 => rest,
+            _ => l,
+        })
+        .collect();
+    Some(block_lines(&text.join("\n")))
+}
+
+fn indent_of(line: &str) -> &str {
+    &line[..line.len() - line.trim_start().len()]
+}
+
+/// Headings, quotes and tables are kept line by line
+fn is_markdown_line(text: &str) -> bool {
+    text.starts_with("# ")
+        || text.starts_with("##")
+        || text.starts_with('>')
+        || text.starts_with('|')
+}
+
+/// Length of the marker of a list item: `-`, `*`, `+`, `1.` or `1)`
+fn list_marker(text: &str) -> Option<usize> {
+    let (marker, rest) = text.split_once(' ').unwrap_or((text, ""));
+    let is_marker = matches!(marker, "-" | "*" | "+")
+        || marker.len() > 1
+            && marker.ends_with(['.', ')'])
+            && marker[..marker.len() - 1]
+                .chars()
+                .all(|c| c.is_ascii_digit());
+    (is_marker && !rest.trim().is_empty()).then_some(marker.len())
+}
+
+fn is_list_item(words: &[String]) -> bool {
+    words
+        .first()
+        .is_some_and(|w| list_marker(&format!("{w} x")).is_some())
+}
+
+/// Trailing comments of a list body, the comments of consecutive lines are aligned
+#[derive(Clone, Default)]
+pub struct CommentGroup {
+    infos: Rc<RefCell<Vec<(LineNumber, ColumnNumber)>>>,
+    reevaluations: Vec<ConditionReevaluation>,
+}
+
+impl std::fmt::Debug for CommentGroup {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CommentGroup")
+            .field("infos", &self.infos)
+            .finish_non_exhaustive()
+    }
+}
+
+impl CommentGroup {
+    /// Spaces before a trailing comment, at least `min`, up to the column of the comment
+    /// on a consecutive line that is furthest right. The comment is not moved further if
+    /// its `width` wouldn't fit into `line_width` anymore.
+    pub fn gen_spaces(&mut self, min: usize, width: usize, line_width: u32) -> PrintItems {
+        let mut items = PrintItems::new();
+        let line = LineNumber::new("trailingComment");
+        let column = ColumnNumber::new("trailingComment");
+        items.push_info(line);
+        items.push_info(column);
+        items.extend(gen_spaces(min));
+
+        let index = {
+            let mut infos = self.infos.borrow_mut();
+            infos.push((line, column));
+            infos.len() - 1
+        };
+        // the remaining spaces are added in halves, so a few conditions cover a wide gap
+        for size in [32, 16, 8, 4, 2, 1] {
+            let infos = self.infos.clone();
+            let mut condition = conditions::if_true(
+                "alignTrailingComment",
+                Rc::new(move |context| {
+                    // all infos are queried, so the condition is reevaluated once they are known
+                    let positions: Vec<Option<(u32, u32)>> = infos
+                        .borrow()
+                        .iter()
+                        .map(|(line, column)| {
+                            let line = context.resolved_line_number(*line);
+                            let column = context.resolved_column_number(*column);
+                            line.zip(column)
+                        })
+                        .collect();
+                    let target = aligned_column(&positions, index)? + min as u32;
+                    let fits = target + width as u32 <= line_width;
+                    Some(fits && target >= context.writer_info.column_number + size)
+                }),
+                gen_spaces(size as usize),
+            );
+            self.reevaluations.push(condition.create_reevaluation());
+            items.push_condition(condition);
+        }
+        items
+    }
+
+    /// Check the spaces of all comments again at the end of the group, the positions of
+    /// the comments after one may have been resolved before the lines above were re-laid out
+    pub fn gen_reevaluations(self) -> PrintItems {
+        let mut items = PrintItems::new();
+        for reevaluation in self.reevaluations {
+            items.push_reevaluation(reevaluation);
+        }
+        items
+    }
+}
+
+/// Rightmost end of the code before the comments on consecutive lines around `index`
+pub fn aligned_column(positions: &[Option<(u32, u32)>], index: usize) -> Option<u32> {
+    let (line, column) = positions.get(index).copied().flatten()?;
+    let mut max = column;
+    for (step, neighbours) in [
+        (-1, positions[..index].iter().rev().collect::<Vec<_>>()),
+        (1, positions[index + 1..].iter().collect()),
+    ] {
+        let mut expected = line as i64;
+        for (l, c) in neighbours.into_iter().map_while(|p| *p) {
+            expected += step;
+            if l as i64 != expected {
+                break;
+            }
+            max = max.max(c);
+        }
+    }
+    Some(max)
+}
+
+#[cfg(test)]
+mod test_comments {
+    use super::*;
+
+    fn paragraph(indent: &str, hang: &str, text: &str) -> Block {
+        Block::Paragraph {
+            indent: indent.to_string(),
+            hang: hang.to_string(),
+            words: text.split_whitespace().map(|w| w.to_string()).collect(),
+        }
+    }
+
+    fn line(text: &str) -> Block {
+        Block::Line(text.to_string())
+    }
+
+    #[test]
+    fn test_paragraphs() {
+        assert_eq!(
+            split_blocks(&["one two", "three", "", "four"], false),
+            vec![
+                paragraph("", "", "one two three"),
+                line(""),
+                paragraph("", "", "four")
+            ]
+        );
+        assert_eq!(
+            split_blocks(&["one", "   two", " ", "   three"], true),
+            vec![
+                paragraph("", "   ", "one two"),
+                line(""),
+                paragraph("   ", "   ", "three")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lists() {
+        assert_eq!(
+            split_blocks(&["items:", "- one", "  more", "10. two", "* three"], false),
+            vec![
+                paragraph("", "", "items:"),
+                paragraph("", "  ", "- one more"),
+                paragraph("", "    ", "10. two"),
+                paragraph("", "  ", "* three")
+            ]
+        );
+        assert_eq!(
+            split_blocks(&["-1 is negative", "-"], false),
+            vec![paragraph("", "", "-1 is negative -")]
+        );
+    }
+
+    #[test]
+    fn test_kept_lines() {
+        assert_eq!(
+            split_blocks(&["```", "let a = 1;", "```", "text"], false),
+            vec![
+                line("```"),
+                line("let a = 1;"),
+                line("```"),
+                paragraph("", "", "text")
+            ]
+        );
+        assert_eq!(
+            split_blocks(
+                &["# Title", "text", "    code", "> quote", "| a | b |"],
+                false
+            ),
+            vec![
+                line("# Title"),
+                paragraph("", "", "text"),
+                line("    code"),
+                line("> quote"),
+                line("| a | b |")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_block_lines() {
+        assert_eq!(
+            block_lines("one\n     two\n       three\n "),
+            vec!["one", "two", "  three"]
+        );
+        assert_eq!(block_lines("\n  one\n\n  two\n"), vec!["one", "", "two"]);
+    }
+
+    #[test]
+    fn test_doc_block_lines() {
+        assert_eq!(doc_block_lines("/** one */"), Some(vec!["one".to_string()]));
+        assert_eq!(
+            doc_block_lines("/**\n   * one\n   *\n   *   two\n   */"),
+            Some(vec!["one".to_string(), "".to_string(), "  two".to_string()])
+        );
+        assert_eq!(
+            doc_block_lines("/** one\n    two */"),
+            Some(vec!["one".to_string(), "two".to_string()])
+        );
+        assert_eq!(doc_block_lines("/* * not a doc comment */"), None);
+        assert_eq!(doc_block_lines("/*** banner ***/"), None);
+        assert_eq!(doc_block_lines("/**/"), None);
+    }
+
+    #[test]
+    fn test_aligned_column() {
+        let positions = [
+            Some((1, 10)),
+            Some((2, 14)),
+            Some((3, 8)),
+            Some((5, 20)),
+            None,
+            Some((7, 4)),
+        ];
+        assert_eq!(aligned_column(&positions, 0), Some(14));
+        assert_eq!(aligned_column(&positions, 2), Some(14));
+        assert_eq!(aligned_column(&positions, 3), Some(20));
+        assert_eq!(aligned_column(&positions, 4), None);
+        assert_eq!(aligned_column(&positions, 5), Some(4));
+    }
+}
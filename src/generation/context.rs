@@ -1,24 +1,87 @@
+use super::comments::CommentGroup;
 use super::helper::*;
 use crate::configuration::Configuration;
 use dprint_core::formatting::*;
 
+/// Block and continuation indentation as multiples of the printer's indent width
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Indentation {
+    /// Indent width of the printer
+    pub unit: u8,
+    /// Indents of a block body
+    pub block: u32,
+    /// Indents of a line that continues an expression
+    pub continuation: u32,
+}
+
+impl Indentation {
+    pub fn from_config(config: &Configuration) -> Self {
+        let indent_width = config.indent_width.max(1);
+        let continuation_width = config.continuation_indent_width.max(1);
+        if config.use_tabs {
+            // a tab is one indent, so the continuation is rounded to whole tabs
+            let continuation = (continuation_width as f32 / indent_width as f32).round();
+            Self {
+                unit: indent_width,
+                block: 1,
+                continuation: (continuation as u32).max(1),
+            }
+        } else {
+            // the printer indents by the largest width both indents are a multiple of
+            let unit = gcd(indent_width, continuation_width);
+            Self {
+                unit,
+                block: (indent_width / unit) as u32,
+                continuation: (continuation_width / unit) as u32,
+            }
+        }
+    }
+}
+
+fn gcd(a: u8, b: u8) -> u8 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
 #[derive(Debug)]
 pub struct Context<'a> {
-    config: &'a Configuration,
+    pub text: &'a str,
+    pub config: &'a Configuration,
+    pub indent: Indentation,
     expect_space: bool,
     force_space: bool,
     possible_newline: bool,
     mode_no_space: bool,
+    // set after an opening bracket, the next block comment follows it without a space
+    no_space_before_comment: bool,
+    // set by an ignore directive, the next declaration is kept as it is
+    ignore_next: bool,
+    // set by an ignore start directive, until the matching end directive
+    ignore_range: bool,
+    // source range of the nodes skipped since the ignore start directive
+    ignored: Option<(usize, usize)>,
+    // trailing comments of the current list body, if they are aligned
+    comment_group: Option<CommentGroup>,
 }
 
 impl<'a> Context<'a> {
-    pub fn new(_text: &'a str, config: &'a Configuration) -> Self {
+    pub fn new(text: &'a str, config: &'a Configuration) -> Self {
         Self {
+            text,
             config,
+            indent: Indentation::from_config(config),
             expect_space: false,
             force_space: false,
             possible_newline: false,
             mode_no_space: false,
+            no_space_before_comment: false,
+            ignore_next: false,
+            ignore_range: false,
+            ignored: None,
+            comment_group: None,
         }
     }
 
@@ -74,8 +137,85 @@ impl<'a> Context<'a> {
         self.possible_newline = false;
     }
 
+    pub fn no_space_before_comment(&mut self) {
+        self.no_space_before_comment = true;
+    }
+
+    pub fn take_no_space_before_comment(&mut self) -> bool {
+        std::mem::take(&mut self.no_space_before_comment)
+    }
+
+    pub fn ignore_next(&mut self) {
+        self.ignore_next = true;
+    }
+
+    pub fn take_ignore_next(&mut self) -> bool {
+        std::mem::take(&mut self.ignore_next)
+    }
+
+    pub fn start_ignore_range(&mut self) {
+        self.ignore_range = true;
+    }
+
+    pub fn is_ignoring_range(&self) -> bool {
+        self.ignore_range
+    }
+
+    /// Extend the ignored range to include a skipped node
+    pub fn skip_ignored(&mut self, start: usize, end: usize) {
+        self.ignored = Some(match self.ignored {
+            Some((s, _)) => (s, end),
+            None => (start, end),
+        });
+    }
+
+    /// End the ignore range and return the source range of all skipped nodes
+    pub fn finish_ignore_range(&mut self) -> Option<(usize, usize)> {
+        self.ignore_range = false;
+        self.ignored.take()
+    }
+
+    /// Start a new group of aligned trailing comments and return the outer one
+    pub fn start_comment_group(&mut self) -> Option<CommentGroup> {
+        self.comment_group.replace(CommentGroup::default())
+    }
+
+    /// Continue with the outer group of aligned trailing comments and return the finished one
+    pub fn finish_comment_group(&mut self, outer: Option<CommentGroup>) -> Option<CommentGroup> {
+        std::mem::replace(&mut self.comment_group, outer)
+    }
+
+    pub fn comment_group(&mut self) -> Option<&mut CommentGroup> {
+        self.comment_group.as_mut()
+    }
+
     pub fn reset_expect(&mut self) {
         self.expect_space = false;
         self.possible_newline = false;
     }
 }
+
+#[cfg(test)]
+mod test_context {
+    use super::*;
+    use crate::configuration::ConfigurationBuilder;
+
+    #[test]
+    fn test_indentation() {
+        let indentation = |indent_width, continuation_indent_width, use_tabs| {
+            let config = ConfigurationBuilder::new()
+                .indent_width(indent_width)
+                .continuation_indent_width(continuation_indent_width)
+                .use_tabs(use_tabs)
+                .build();
+            let indent = Indentation::from_config(&config);
+            (indent.unit, indent.block, indent.continuation)
+        };
+        assert_eq!(indentation(2, 2, false), (2, 1, 1));
+        assert_eq!(indentation(2, 4, false), (2, 1, 2));
+        assert_eq!(indentation(4, 2, false), (2, 2, 1));
+        assert_eq!(indentation(4, 6, false), (2, 2, 3));
+        assert_eq!(indentation(4, 8, true), (4, 1, 2));
+        assert_eq!(indentation(4, 2, true), (4, 1, 1));
+    }
+}
@@ -1,9 +1,17 @@
 use dprint_core::formatting::*;
+use std::rc::Rc;
 
+use super::binary::*;
+use super::comments::{block_lines, doc_block_lines, split_blocks, Block};
 use super::context::Context;
 use super::helper::*;
-use crate::configuration::Configuration;
-use crate::motoko_parser::{Node, NodeType::*};
+use super::numbers::format_number;
+use crate::configuration::{
+    Configuration, DocCommentStyle, OperatorSpacing, Semicolons, TrailingCommas,
+    TrailingCommentSpacing,
+};
+use crate::motoko_parser::ast;
+use crate::motoko_parser::{Node, NodeType, NodeType::*};
 
 #[cfg(debug_assertions)]
 pub fn generate(nodes: &Vec<Node>, text: &str, config: &Configuration) -> PrintItems {
@@ -30,18 +38,37 @@ pub fn generate(nodes: &Vec<Node>, text: &str, config: &Configuration) -> PrintI
 fn gen_node<'a>(node: &Node, context: &mut Context) -> PrintItems {
     let mut items = PrintItems::new();
 
+    if matches!(
+        node.node_type,
+        Import | Declaration | DeclarationField | InvalidPart
+    ) && context.take_ignore_next()
+    {
+        return gen_unformatted(node, context);
+    }
+
     items.extend(match &node.node_type {
         //Motoko => gen_debug(&node, context),
         Motoko => gen_nodes(&node.children, context),
         Header => gen_nodes(&node.children, context),
         Program => gen_program(&node, context),
-        ImportList => gen_list_body(";", &node.children, context, true, 3, false),
-        DeclarationList => gen_list_body(";", &node.children, context, true, 3, false),
+        ImportList if context.config.sort_imports && can_sort_imports(node, context.config) => {
+            gen_sorted_imports(node, context)
+        }
+        // an ignore range can continue from the imports into the declarations
+        ImportList => {
+            let sep = FinalSeparator::MultiLine;
+            gen_list_body(";", &node.children, context, true, 3, sep, true)
+        }
+        DeclarationList => {
+            let sep = FinalSeparator::MultiLine;
+            gen_list_body(";", &node.children, context, true, 3, sep, false)
+        }
 
         Import => gen_import(&node, context),
         Declaration => gen_nodes(&node.children, context),
 
         Id => gen_id(&node, context),
+        InvalidPart => gen_unformatted(&node, context),
 
         TypeVariant => {
             context.reset_possible_newline();
@@ -62,25 +89,37 @@ fn gen_node<'a>(node: &Node, context: &mut Context) -> PrintItems {
         Text => gen_id(&node, context),
         COMMENT => gen_nodes(&node.children, context),
         Comment => gen_comment(&node, context),
-        LineComment => gen_comment_line("//", &node, context),
+        LineComment => {
+            mark_ignore_directive(node, context);
+            gen_comment_line("//", &node, context)
+        }
         DocComment => gen_comment_line("///", &node, context),
-        BlockComment => gen_comment_block(&node, context),
+        BlockComment => {
+            mark_ignore_directive(node, context);
+            gen_comment_block(&node, context)
+        }
         LineCommentContent => gen_id_trim_comment(true, &node, context),
         DocCommentContent => gen_id_trim_comment(false, &node, context),
         BlockCommentContent => gen_id_multiline(&node, context),
         SpacedComment => gen_spaced_comment(&node, context),
+        Lit | Nat if node.original.starts_with(|c: char| c.is_ascii_digit()) => {
+            gen_number(node, context)
+        }
         Lit | Nat => gen_id(&node, context),
         ShouldNewline => gen_should_newline(&node, context),
         PatternField => gen_pattern_field(&node, context),
         ExpNonDec => gen_exp_non_dec(&node, context),
 
+        ExpBin if is_pipe_chain(node) => gen_pipe_chain(node, context),
+        ExpBin if node.has_child(&ExpBinContinue) => gen_exp_bin(node, context),
+
         // ExpBin => gen_debug(&node, context),
         Exp | ExpNonVar | ExpPlain | ExpBin | ExpNullary | ExpNest | ExpPost | DeclarationField
         | Type | TypeNoBin | TypeUn | TypePre | TypeItem | ExpBinContinue | ClassBody | Case
         | DeclarationVar | TypeField | VarExpNonVar | Stability | FuncSort => {
             gen_nodes(&node.children, context)
         }
-        Catch => gen_catch(&node, context),
+        Catch | Finally => gen_catch(&node, context),
 
         SharedPattern | SharedPattern2 => gen_shared_pattern(&node, context),
         PatternUn => gen_pattern_un(&node.children, context),
@@ -90,39 +129,32 @@ fn gen_node<'a>(node: &Node, context: &mut Context) -> PrintItems {
 
         DeclarationNonVar => gen_declaration_non_var(&node, context),
 
-        //ExpPostContinue
-        //    if node.has_descendant(&ExpPostList)
-        //        && (node.original.contains(" <") || node.original.contains(" >")) =>
-        //{
-        //    // TODO!: BinOp / RelOp can get miss parsed as a ExpPostList
-        //    // This is a bug in the parser caused by it's
-        //    gen_unformatted(node, context)
-        //}
         ExpPostContinue | ExpPostList => {
-            if !node.starts_with(&Id) {
+            if !node.starts_with(&Id) && !node.starts_with(&KeywordUnderscore) {
                 context.reset_expect();
             }
             gen_nodes_maybe_perenthesized(&node, context)
         }
 
         ObjSort | Visibility | KeywordActor | KeywordAnd | KeywordAssert | KeywordAsync
-        | KeywordAwait | KeywordBreak | KeywordCase | KeywordCatch | KeywordClass
-        | KeywordContinue | KeywordDebug | KeywordDebugShow | KeywordDo | KeywordElse
-        | KeywordFlexible | KeywordFalse | KeywordFor | KeywordFromCandid | KeywordFunc
-        | KeywordIf | KeywordIgnore | KeywordImport | KeywordIn | KeywordModule | KeywordNot
-        | KeywordNull | KeywordObject | KeywordOr | KeywordLabel | KeywordLet | KeywordLoop
-        | KeywordPrivate | KeywordPublic | KeywordQuery | KeywordReturn | KeywordShared
-        | KeywordStable | KeywordSwitch | KeywordSystem | KeywordThrow | KeywordToCandid
-        | KeywordTrue | KeywordTry | KeywordType | KeywordVar | KeywordWhile => {
-            gen_keyword(node, context)
-        }
+        | KeywordAsyncStar | KeywordAwait | KeywordAwaitStar | KeywordBreak | KeywordCase
+        | KeywordCatch | KeywordClass | KeywordComposite | KeywordContinue | KeywordDebug
+        | KeywordDebugShow | KeywordDo | KeywordElse | KeywordFinally | KeywordFlexible
+        | KeywordFalse | KeywordFor | KeywordFromCandid | KeywordFunc | KeywordIf
+        | KeywordIgnore | KeywordImport | KeywordIn | KeywordModule | KeywordNot | KeywordNull
+        | KeywordObject | KeywordOr | KeywordLabel | KeywordLet | KeywordLoop
+        | KeywordPersistent | KeywordPrivate | KeywordPublic | KeywordQuery | KeywordReturn
+        | KeywordShared | KeywordStable | KeywordSwitch | KeywordThrow | KeywordToCandid
+        | KeywordTransient | KeywordTrue | KeywordTry | KeywordType | KeywordVar | KeywordWhile
+        | KeywordWith => gen_keyword(node, context),
 
         Colon | Arrow | BinAssign | EqualSign => {
             context.expect_space();
             gen_keyword(node, context)
         }
 
-        HashTag | KeywordUnderscore => gen_id(node, context),
+        // `system` is only reached as a type argument, see `Visibility` for `system func`
+        HashTag | KeywordUnderscore | KeywordSystem => gen_id(node, context),
 
         Questionmark | UnOp => {
             let i = gen_id(node, context);
@@ -135,6 +167,9 @@ fn gen_node<'a>(node: &Node, context: &mut Context) -> PrintItems {
             gen_keyword(node, context)
         }
 
+        ExpObj if node.has_child(&ExpObjBases) => gen_exp_obj_bases(node, context),
+        Parenthetical => gen_parenthetical(node, context),
+
         Block | ObjBody | ExpObj | TypeObj => {
             let force_multiline = count_newlines(&node.original) > 0;
             gen_list(
@@ -160,9 +195,9 @@ fn gen_node<'a>(node: &Node, context: &mut Context) -> PrintItems {
             context.reset_expect();
             gen_list("(", ",", ")", &node.children, context, false, true, 99)
         }
-        Dot | TypeBindList => gen_id_no_space(&node, context),
+        Dot => gen_id_no_space(&node, context),
 
-        TypeArgs => {
+        TypeArgs | TypeBindList => {
             context.reset_expect();
             let force_multiline = count_newlines(&node.original) > 0;
             gen_list(
@@ -200,13 +235,17 @@ fn gen_program(node: &Node, context: &mut Context) -> PrintItems {
 
     let mut after_import = false;
     let mut after_declarations = false;
+    let mut after_comment = false;
+    let mut after_doc_comment = false;
     let mut lines = 0;
 
     for n in node.children.iter() {
         match n.node_type {
             ImportList => {
                 items.extend(gen_node(n, context));
-                after_import = count_not_ignored_or_comment(&n.children) > 0;
+                // skipped imports are kept together with the ignored declarations
+                after_import =
+                    count_not_ignored_or_comment(&n.children) > 0 && !context.is_ignoring_range();
                 lines = 0;
             }
             DeclarationList => {
@@ -214,6 +253,11 @@ fn gen_program(node: &Node, context: &mut Context) -> PrintItems {
                     if after_import {
                         items.extend(gen_newlines(2));
                         after_import = false;
+                    } else if after_comment {
+                        // comment between imports and declarations
+                        let max = if after_doc_comment { 1 } else { 2 };
+                        items.extend(gen_newlines(lines.clamp(1, max)));
+                        context.reset_expect();
                     }
                     lines = 0;
                     after_declarations = true;
@@ -230,6 +274,8 @@ fn gen_program(node: &Node, context: &mut Context) -> PrintItems {
                     lines = 0;
                 }
                 items.extend(gen_node(n, context));
+                after_comment = true;
+                after_doc_comment = ends_with_doc_comment(n);
             }
         }
     }
@@ -239,16 +285,152 @@ fn gen_program(node: &Node, context: &mut Context) -> PrintItems {
     items
 }
 
+/// An import with the comments before it and on the same line after it
+struct ImportEntry<'a> {
+    leading: Vec<&'a Node>,
+    import: &'a Node,
+    trailing: Vec<&'a Node>,
+    path: String,
+}
+
+// Imports are grouped by `mo:base`, other packages, canisters and relative paths
+fn import_group(path: &str) -> usize {
+    if path == "mo:base" || path.starts_with("mo:base/") {
+        0
+    } else if path.starts_with("mo:") {
+        1
+    } else if path.starts_with("canister:") {
+        2
+    } else {
+        3
+    }
+}
+
+// Reordering imports would move ignored parts around
+fn can_sort_imports(node: &Node, config: &Configuration) -> bool {
+    !node.children.iter().any(|n| {
+        has_directive(n, &config.ignore_directive)
+            || has_directive(n, &config.ignore_start_directive)
+    })
+}
+
+fn gen_sorted_imports(node: &Node, context: &mut Context) -> PrintItems {
+    let mut items = PrintItems::new();
+
+    let mut entries: Vec<ImportEntry> = vec![];
+    let mut comments = vec![];
+    let mut after_newline = true;
+    for n in node.children.iter() {
+        match n.node_type {
+            WHITESPACE => after_newline = after_newline || n.original.contains('\n'),
+            Import => {
+                entries.push(ImportEntry {
+                    leading: std::mem::take(&mut comments),
+                    import: n,
+                    trailing: vec![],
+                    path: n
+                        .get_one_child(&Text)
+                        .map(|t| t.original.trim().trim_matches('"').to_string())
+                        .unwrap_or_default(),
+                });
+                after_newline = false;
+            }
+            _ if is_comment(n) => {
+                // a group of comments can contain a trailing comment and the next import's comments
+                let mut parts = vec![];
+                split_comments(n, &mut after_newline, &mut parts);
+                for (newline_before, part) in parts {
+                    match entries.last_mut() {
+                        Some(entry) if !newline_before && comments.is_empty() => {
+                            entry.trailing.push(part)
+                        }
+                        _ => comments.push(part),
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    entries.sort_by(|a, b| (import_group(&a.path), &a.path).cmp(&(import_group(&b.path), &b.path)));
+
+    let mut previous_group = None;
+    for entry in entries.iter() {
+        let group = import_group(&entry.path);
+        if let Some(previous) = previous_group {
+            items.extend(gen_newlines(if previous == group { 1 } else { 2 }));
+        }
+        previous_group = Some(group);
+        for comment in entry.leading.iter() {
+            items.extend(gen_node(comment, context));
+            items.push_signal(Signal::NewLine);
+        }
+        items.extend(gen_node(entry.import, context));
+        items.push_str(";");
+        context.reset_expect();
+        for comment in entry.trailing.iter() {
+            items.extend(gen_node(comment, context));
+        }
+    }
+
+    // comments after the last import
+    for comment in comments {
+        items.extend(gen_newlines(if entries.is_empty() { 0 } else { 2 }));
+        items.extend(gen_node(comment, context));
+    }
+
+    items
+}
+
+// Single comments of a group of comments, each with whether it starts on a new line
+fn split_comments<'a>(node: &'a Node, newline: &mut bool, parts: &mut Vec<(bool, &'a Node)>) {
+    for n in node.children.iter() {
+        match n.node_type {
+            LineComment | BlockComment | DocComment => {
+                parts.push((*newline, n));
+                *newline = false;
+            }
+            WHITESPACE => *newline = *newline || n.original.contains('\n'),
+            _ => split_comments(n, newline, parts),
+        }
+    }
+}
+
+// `{ map; find }` becomes `{ find; map }`, unless there are comments in between
+fn sort_pattern_fields(node: &Node) -> Node {
+    let mut sorted = node.clone();
+    if node.children.iter().any(is_comment) {
+        return sorted;
+    }
+    let mut fields: Vec<Node> = node
+        .children
+        .iter()
+        .filter(|n| n.node_type == PatternField)
+        .cloned()
+        .collect();
+    fields.sort_by(|a, b| a.original.trim().cmp(b.original.trim()));
+    let mut fields = fields.into_iter();
+    for child in sorted.children.iter_mut() {
+        if child.node_type == PatternField {
+            *child = fields.next().unwrap();
+        }
+    }
+    sorted
+}
+
 fn gen_import(node: &Node, context: &mut Context) -> PrintItems {
     let mut items = PrintItems::new();
     items.push_str("import");
     context.expect_space();
-    items.push_signal(Signal::StartIndent);
-    items.push_signal(Signal::StartIndent);
+    items.extend(signal_times(Signal::StartIndent, context.indent.block * 2));
 
     for n in node.children.iter() {
         match n.node_type {
             KeywordImport => { /* already added */ }
+            PatternNullary if context.config.sort_imports => {
+                items.extend(gen_pattern_nullary(&sort_pattern_fields(n), context));
+                context.expect_space();
+            }
             PatternNullary => {
                 items.extend(gen_pattern_nullary(n, context));
                 context.expect_space();
@@ -264,8 +446,7 @@ fn gen_import(node: &Node, context: &mut Context) -> PrintItems {
         }
     }
 
-    items.push_signal(Signal::FinishIndent);
-    items.push_signal(Signal::FinishIndent);
+    items.extend(signal_times(Signal::FinishIndent, context.indent.block * 2));
 
     items
 }
@@ -287,6 +468,17 @@ fn gen_id(node: &Node, context: &mut Context) -> PrintItems {
     items
 }
 
+fn gen_number(node: &Node, context: &mut Context) -> PrintItems {
+    let mut items = PrintItems::new();
+    items.extend(context.gen_expected_space());
+    items.push_str(&format_number(
+        node.original.trim(),
+        context.config.number_separators,
+    ));
+    context.expect_space();
+    items
+}
+
 fn gen_id_no_space(node: &Node, context: &mut Context) -> PrintItems {
     context.reset_expect();
     let items = gen_id(node, context);
@@ -354,11 +546,26 @@ fn _gen_id_trim_each(node: &Node, context: &mut Context) -> PrintItems {
     items
 }
 
+/// Print the source of a node exactly as it is in the input
 fn gen_unformatted(node: &Node, context: &mut Context) -> PrintItems {
+    gen_verbatim(node.start, node.end, context)
+}
+
+// Use the original source text between `start` and `end`
+fn gen_verbatim(start: usize, end: usize, context: &mut Context) -> PrintItems {
     let mut items = PrintItems::new();
-    items.push_signal(Signal::StartIgnoringIndent);
-    items.extend(gen_id(node, context));
-    items.push_signal(Signal::FinishIgnoringIndent);
+    items.extend(context.gen_expected_space());
+    let text = context.text[start..end].trim_end();
+    // the first line starts at the current indentation, all others are kept as they are
+    let (first, rest) = text.split_once('\n').unwrap_or((text, ""));
+    items.extend(ir_helpers::gen_from_string(first.trim_end()));
+    if text.contains('\n') {
+        items.push_signal(Signal::StartIgnoringIndent);
+        items.push_signal(Signal::NewLine);
+        items.extend(ir_helpers::gen_from_string(rest));
+        items.push_signal(Signal::FinishIgnoringIndent);
+    }
+    context.expect_space();
     items
 }
 
@@ -372,7 +579,10 @@ fn gen_id_multiline(node: &Node, _context: &mut Context) -> PrintItems {
     } else {
         for (i, l) in lines.split("\n").enumerate() {
             if l.trim() == "" {
-                // ignore
+                // the indentation of the closing line is kept, e.g. for ` */`
+                if i == len - 1 {
+                    items.push_str(l.trim_end_matches('\r'));
+                }
             } else if i == 0 && l.starts_with(" ") {
                 items.push_str(l.trim_end())
             } else {
@@ -402,7 +612,7 @@ fn gen_keyword(node: &Node, context: &mut Context) -> PrintItems {
 
 fn gen_comment_line(pre: &str, node: &Node, context: &mut Context) -> PrintItems {
     let mut items = PrintItems::new();
-    let spaces = gen_spaces(2);
+    let spaces = gen_trailing_comment_spaces(node, context);
     context.expect_space();
     items.extend(if_not_start_of_line(spaces));
 
@@ -416,7 +626,56 @@ fn gen_comment_line(pre: &str, node: &Node, context: &mut Context) -> PrintItems
     items
 }
 
+/// Spaces between code and a trailing line comment
+fn gen_trailing_comment_spaces(node: &Node, context: &mut Context) -> PrintItems {
+    match context.config.trailing_comment_spacing {
+        TrailingCommentSpacing::Fixed => gen_spaces(2),
+        TrailingCommentSpacing::Preserve => {
+            let before = &context.text[..node.start];
+            let spaces = before.len() - before.trim_end_matches([' ', '\t']).len();
+            gen_spaces(spaces.max(2))
+        }
+        TrailingCommentSpacing::AlignInGroup if is_trailing_comment(node) => {
+            let width = node
+                .original
+                .lines()
+                .next()
+                .unwrap_or_default()
+                .chars()
+                .count();
+            let line_width = context.config.line_width;
+            match context.comment_group() {
+                Some(group) => group.gen_spaces(2, width, line_width),
+                None => gen_spaces(2),
+            }
+        }
+        TrailingCommentSpacing::AlignInGroup => gen_spaces(2),
+    }
+}
+
+// Ignore directives are handled by `gen_node` and `gen_list_body`.
+// A directive after code on the same line doesn't apply to the next node.
+fn mark_ignore_directive(node: &Node, context: &mut Context) {
+    let config = context.config;
+    if is_trailing_comment(node) {
+        return;
+    }
+    if is_directive(node, &config.ignore_directive) {
+        context.ignore_next();
+    } else if is_directive(node, &config.ignore_start_directive) {
+        context.start_ignore_range();
+    }
+}
+
 fn gen_comment(node: &Node, context: &mut Context) -> PrintItems {
+    if let Some(items) = gen_doc_comment(node, context) {
+        return items;
+    }
+    if context.config.comment_wrap && !is_trailing_comment(node) {
+        if let Some(items) = gen_wrapped_comment_lines(node, context) {
+            return items;
+        }
+    }
     let mut items = PrintItems::new();
 
     for n in node.children.iter() {
@@ -428,17 +687,249 @@ fn gen_comment(node: &Node, context: &mut Context) -> PrintItems {
     items
 }
 
+fn is_any_directive(node: &Node, config: &Configuration) -> bool {
+    [
+        &config.ignore_directive,
+        &config.ignore_file_directive,
+        &config.ignore_start_directive,
+        &config.ignore_end_directive,
+    ]
+    .iter()
+    .any(|directive| is_directive(node, directive))
+}
+
+/// Text of consecutive comments of the same kind, e.g. `//`, on the next lines and
+/// the rest of the comments after them. Returns `None` if the first comment is a directive.
+fn collect_comment_lines<'a>(
+    node: &'a Node,
+    kind: &NodeType,
+    context: &Context,
+) -> Option<(Vec<&'a str>, Option<&'a Node>)> {
+    let mut lines = vec![];
+    let mut comment = node;
+    loop {
+        let line = comment.children.first()?;
+        if &line.node_type != kind || is_any_directive(line, context.config) {
+            return None;
+        }
+        let content = line
+            .children
+            .iter()
+            .find(|n| matches!(n.node_type, LineCommentContent | DocCommentContent))
+            .map_or("", |n| n.original.trim_end());
+        let content = match kind {
+            LineComment => content.strip_prefix([' ', '\t']).unwrap_or(content),
+            _ => content,
+        };
+        lines.push(content);
+
+        // continue with the next comment if it is on the next line
+        let spaced = match comment.children.get(1) {
+            Some(spaced) => spaced,
+            None => return Some((lines, None)),
+        };
+        let next = spaced.children.iter().find(|n| n.node_type == Comment);
+        let newlines = spaced
+            .children
+            .iter()
+            .filter(|n| n.node_type == WHITESPACE)
+            .map(|n| count_newlines(&n.original))
+            .sum::<usize>();
+        match next {
+            Some(next)
+                if newlines == 1
+                    && next.children.first().is_some_and(|n| {
+                        &n.node_type == kind && !is_any_directive(n, context.config)
+                    }) =>
+            {
+                comment = next
+            }
+            _ => return Some((lines, Some(spaced))),
+        }
+    }
+}
+
+/// Re-wrap the text of consecutive `//` comments on their own lines.
+/// Returns `None` if the first comment isn't one of them, e.g. a directive.
+fn gen_wrapped_comment_lines(node: &Node, context: &mut Context) -> Option<PrintItems> {
+    let (lines, rest) = collect_comment_lines(node, &LineComment, context)?;
+
+    let mut items = PrintItems::new();
+    items.extend(if_not_start_of_line(gen_spaces(2)));
+    items.extend(gen_reflowed("//", split_blocks(&lines, false), context));
+    items.push_signal(Signal::ExpectNewLine);
+    context.expect_space();
+    if let Some(rest) = rest {
+        items.extend(gen_node(rest, context));
+    }
+    Some(items)
+}
+
+/// Doc comments `///`, `//!` and `/** … */` in the configured style.
+/// Returns `None` if the first comment isn't a doc comment or follows code on its line,
+/// and for `/** … */` if the style is preserved.
+fn gen_doc_comment(node: &Node, context: &mut Context) -> Option<PrintItems> {
+    let first = node.children.first()?;
+    if is_trailing_comment(first) {
+        return None;
+    }
+    let (lines, rest): (Vec<String>, Option<&Node>) = match first.node_type {
+        DocComment => {
+            let (lines, rest) = collect_comment_lines(node, &DocComment, context)?;
+            (lines.into_iter().map(|l| l.to_string()).collect(), rest)
+        }
+        BlockComment if context.config.doc_comment_style == DocCommentStyle::Preserve => {
+            return None
+        }
+        BlockComment => (
+            doc_block_lines(first.original.trim())?,
+            node.children.get(1),
+        ),
+        _ => return None,
+    };
+
+    let mut items = PrintItems::new();
+    items.extend(if_not_start_of_line(gen_trailing_comment_spaces(
+        first, context,
+    )));
+    match context.config.doc_comment_style {
+        DocCommentStyle::Block => match lines.as_slice() {
+            [] => items.push_str("/** */"),
+            [line] => items.push_str(&format!("/** {line} */")),
+            _ => {
+                items.push_str("/**");
+                items.push_signal(Signal::NewLine);
+                let body = gen_comment_lines("", &lines, context);
+                items.extend(ir_helpers::with_indent_times(body, context.indent.block));
+                items.push_signal(Signal::NewLine);
+                items.push_str("*/");
+            }
+        },
+        _ if lines.is_empty() => items.push_str("///"),
+        _ => items.extend(gen_comment_lines("///", &lines, context)),
+    }
+    // the declaration that follows is documented by the comment
+    items.push_signal(Signal::ExpectNewLine);
+    context.expect_space();
+    if let Some(rest) = rest {
+        items.extend(gen_node(rest, context));
+    }
+    Some(items)
+}
+
+/// Lines of a comment that start with the prefix, re-wrapped if configured
+fn gen_comment_lines(prefix: &str, lines: &[String], context: &mut Context) -> PrintItems {
+    let blocks = if context.config.comment_wrap {
+        split_blocks(&lines.iter().map(|l| l.as_str()).collect::<Vec<_>>(), false)
+    } else {
+        lines.iter().map(|l| Block::Line(l.to_string())).collect()
+    };
+    gen_reflowed(prefix, blocks, context)
+}
+
+/// Print the lines of a comment, the words of paragraphs are moved to the next line if they
+/// don't fit into the line width. Each line starts with the prefix, e.g. `//`.
+fn gen_reflowed(prefix: &str, blocks: Vec<Block>, context: &mut Context) -> PrintItems {
+    let mut items = PrintItems::new();
+    let line_width = context.config.line_width;
+    let start = if prefix.is_empty() {
+        String::new()
+    } else {
+        format!("{prefix} ")
+    };
+    for (i, block) in blocks.into_iter().enumerate() {
+        if i > 0 {
+            items.push_signal(Signal::NewLine);
+        }
+        match block {
+            Block::Line(line) if line.is_empty() => {
+                if !prefix.is_empty() {
+                    items.push_str(prefix)
+                }
+            }
+            Block::Line(line) => items.push_str(&format!("{start}{line}")),
+            Block::Paragraph {
+                indent,
+                hang,
+                words,
+            } => {
+                let mut words = words.into_iter();
+                let first = words.next().unwrap_or_default();
+                items.push_str(&format!("{start}{indent}{first}"));
+                for word in words {
+                    let width = word.chars().count() as u32;
+                    let mut next_line = PrintItems::new();
+                    next_line.push_signal(Signal::NewLine);
+                    next_line.push_str(&format!("{start}{hang}{word}"));
+                    items.push_condition(conditions::if_true_or(
+                        "commentWordFits",
+                        Rc::new(move |context| {
+                            Some(context.writer_info.column_number + 1 + width <= line_width)
+                        }),
+                        format!(" {word}").into(),
+                        next_line,
+                    ));
+                }
+            }
+        }
+    }
+    items
+}
+
+/// Lines of a block comment that can be written as line comments, i.e. no code follows it on
+/// the same line, it isn't empty and contains no nested comment
+fn block_comment_lines(node: &Node, context: &Context) -> Option<Vec<String>> {
+    let content = &node
+        .children
+        .iter()
+        .find(|n| n.node_type == BlockCommentContent)?
+        .original;
+    let after = &context.text[node.end..];
+    let rest_of_line = &after[..after.find('\n').unwrap_or(after.len())];
+    // doc comments are only rewritten in the configured doc comment style
+    if !rest_of_line.trim().is_empty()
+        || content.contains("/*")
+        || doc_block_lines(node.original.trim()).is_some()
+    {
+        return None;
+    }
+
+    let lines = block_lines(content);
+    (!lines.is_empty()).then_some(lines)
+}
+
 fn gen_comment_block(node: &Node, context: &mut Context) -> PrintItems {
     let mut items = PrintItems::new();
+    if context.config.prefer_line_comments {
+        if let Some(lines) = block_comment_lines(node, context) {
+            items.extend(if_not_start_of_line(gen_trailing_comment_spaces(
+                node, context,
+            )));
+            items.extend(gen_comment_lines("//", &lines, context));
+            items.push_signal(Signal::ExpectNewLine);
+            context.expect_space();
+            return items;
+        }
+    }
     //items.extend(gen_expected_space(context));
 
     // inline block comments only with one space
-    items.extend(if_not_start_of_line(gen_spaces(1)));
-    items.push_signal(Signal::StartIgnoringIndent);
-
+    if !context.take_no_space_before_comment() {
+        items.extend(if_not_start_of_line(gen_spaces(1)));
+    }
     items.push_str("/*");
-    items.push_signal(Signal::SpaceIfNotTrailing);
-    // TODO: wrap / reflow text?
+    // the lines of the comment keep their original indentation
+    items.push_signal(Signal::StartIgnoringIndent);
+    let content = node
+        .children
+        .iter()
+        .find(|n| n.node_type == BlockCommentContent)
+        .map_or("", |n| n.original.as_str());
+    // stars next to the delimiters are part of them, e.g. `/*** banner ***/`
+    let is_empty = node.original == "/**/";
+    if is_empty || !node.original.starts_with("/**") {
+        items.push_signal(Signal::SpaceIfNotTrailing);
+    }
 
     let mut add_linebreak = false;
     for n in node.children.iter() {
@@ -446,10 +937,26 @@ fn gen_comment_block(node: &Node, context: &mut Context) -> PrintItems {
             WHITESPACE => {
                 add_linebreak = true;
             }
+            // single line block comments are inline, nested ones are kept as they are
+            BlockCommentContent
+                if context.config.comment_wrap
+                    && n.original.contains('\n')
+                    && !n.original.contains("/*") =>
+            {
+                let lines: Vec<&str> = n.original.split('\n').collect();
+                items.extend(gen_reflowed("", split_blocks(&lines, true), context));
+            }
             _ => items.extend(gen_node(n, context)),
         }
     }
-    items.extend(if_not_start_of_line(Signal::SpaceIfNotTrailing.into()));
+    // the column can't tell whether a line of the comment is empty, because its
+    // indentation is ignored, so look at the last line of the text instead
+    match content.rsplit_once('\n') {
+        _ if !is_empty && node.original.ends_with("**/") => {}
+        Some((_, last)) if last.trim().is_empty() => {}
+        Some(_) => items.push_signal(Signal::SpaceIfNotTrailing),
+        None => items.extend(if_not_start_of_line(Signal::SpaceIfNotTrailing.into())),
+    }
     items.push_str("*/");
     context.expect_space();
     items.push_signal(Signal::FinishIgnoringIndent);
@@ -486,9 +993,22 @@ fn gen_should_newline(node: &Node, context: &mut Context) -> PrintItems {
 }
 
 fn gen_declaration_non_var(node: &Node, context: &mut Context) -> PrintItems {
-    if node.has_child(&KeywordLet) {
+    let kind = ast::DecKind::of(node);
+    if kind == ast::DecKind::Let {
         return gen_let_statement(node, context);
     }
+    // `(with migration = …)` goes on its own line before the actor
+    let mut header = PrintItems::new();
+    if let Some(parenthetical) = node
+        .children
+        .first()
+        .filter(|n| n.node_type == Parenthetical)
+    {
+        header.extend(gen_with_fields("(", ")", false, parenthetical, context));
+        header.push_signal(Signal::NewLine);
+        context.reset_expect();
+    }
+
     let force_multiline = count_newlines(&node.original) > 0;
     let mut items = MultiLineGroup::new(force_multiline, 0, false, "dec_non_var");
     //TODO: this is ugly. abstract it
@@ -497,16 +1017,18 @@ fn gen_declaration_non_var(node: &Node, context: &mut Context) -> PrintItems {
     items.push_signal(Signal::StartNewLineGroup);
     let mut groups = 3;
     let mut indent = false;
-    if !node.has_child(&KeywordType) {
-        items.push_signal(Signal::QueueStartIndent);
+    let continuation = context.indent.continuation;
+    if kind != ast::DecKind::Type {
+        items.extend(signal_times(Signal::QueueStartIndent, continuation));
         indent = true;
     }
 
     for n in node.children.iter() {
         match n.node_type {
+            Parenthetical if n.start == node.start => {}
             ObjBody | FuncBody | ClassBody => {
                 if indent {
-                    items.push_signal(Signal::FinishIndent);
+                    items.extend(signal_times(Signal::FinishIndent, continuation));
                     indent = false;
                 }
                 items.extend(gen_node(n, context));
@@ -542,7 +1064,7 @@ fn gen_declaration_non_var(node: &Node, context: &mut Context) -> PrintItems {
     }
 
     if indent {
-        items.push_signal(Signal::FinishIndent);
+        items.extend(signal_times(Signal::FinishIndent, continuation));
     }
 
     while groups > 0 {
@@ -550,23 +1072,52 @@ fn gen_declaration_non_var(node: &Node, context: &mut Context) -> PrintItems {
         items.push_signal(Signal::FinishNewLineGroup);
     }
 
-    items.take()
+    header.extend(items.take());
+    header
 }
 
 fn gen_let_statement(node: &Node, context: &mut Context) -> PrintItems {
-    let mut items = MultiLineGroup::new(false, 1, true, "let_statement");
+    // pipe chains are indented by their own group
+    let is_pipe = node.children.iter().any(is_pipe_exp);
+    let indent = if is_pipe {
+        0
+    } else {
+        context.indent.continuation
+    };
+    let mut items = MultiLineGroup::new(false, indent, true, "let_statement");
+    // let-else: the else branch is not part of the group,
+    // so a multi line block does not move the expression to the next line
+    let mut else_items = PrintItems::new();
 
     for n in node.children.iter() {
         match n.node_type {
             EqualSign => {
                 items.extend(gen_node(&n, context));
-                items.possible_newline();
+                // pipe chains break before each stage instead
+                if !is_pipe {
+                    items.possible_newline();
+                }
             }
+            KeywordElse => {
+                context.possible_newline();
+                else_items.extend(gen_node(n, context));
+            }
+            ExpNest => {
+                if n.has_child(&Block) || n.starts_with(&KeywordIf) {
+                    else_items.extend(gen_node(n, context))
+                } else {
+                    let block = context.indent.block;
+                    else_items.extend(ir_helpers::with_indent_times(gen_node(n, context), block))
+                }
+            }
+            _ if !else_items.is_empty() => else_items.extend(gen_node(n, context)),
             _ => items.extend(gen_node(&n, context)),
         }
     }
 
-    items.take()
+    let mut items = items.take();
+    items.extend(else_items);
+    items
 }
 
 fn gen_pattern_nullary(node: &Node, context: &mut Context) -> PrintItems {
@@ -655,6 +1206,189 @@ fn gen_catch(node: &Node, context: &mut Context) -> PrintItems {
     items
 }
 
+/// Record extension and merging, e.g. `{ a and b with x = 1 }`
+fn gen_exp_obj_bases(node: &Node, context: &mut Context) -> PrintItems {
+    gen_with_fields("{", "}", context.config.bracket_spacing, node, context)
+}
+
+/// Parenthetical attributes of a call or actor, e.g. `(with cycles = 1_000)`
+fn gen_parenthetical(node: &Node, context: &mut Context) -> PrintItems {
+    let items = gen_with_fields("(", ")", false, node, context);
+    // keep the parenthetical attached to the expression it modifies
+    context.reset_expect();
+    context.force_space();
+    items
+}
+
+/// Bases followed by `with` and a list of fields, surrounded by `start` and `end`
+fn gen_with_fields(
+    start: &str,
+    end: &str,
+    space: bool,
+    node: &Node,
+    context: &mut Context,
+) -> PrintItems {
+    let force_multiline = count_newlines(&node.original) > 0;
+    let mut items = MultiLineGroup::new(force_multiline, 0, false, "with_fields");
+
+    items.extend(context.gen_expected_space());
+    items.push_str(start);
+    items.possible_newline();
+    if space {
+        context.force_space_or_newline();
+    } else {
+        context.reset_expect();
+    }
+
+    let has_bases = node.has_child(&ExpObjBases) || node.has_child(&ExpPost);
+    let mut body = MultiLineGroup::new(force_multiline, 0, false, "with_fields_body");
+    let children = node.children_without_outer();
+    let mut fields = None;
+    for child in children.iter() {
+        match child.node_type {
+            ExpObjBases => body.extend(gen_nodes(&child.children, context)),
+            KeywordWith => body.extend(gen_keyword(child, context)),
+            ExpObjFields => fields = Some(child),
+            _ => body.extend(gen_node(child, context)),
+        }
+    }
+    // the fields are in the group of the bases, they break like the fields of an object
+    // if the bases and the fields don't fit on one line
+    let body = match fields {
+        Some(fields) => {
+            // `(with x = 1)` has no bases to break after
+            if has_bases {
+                body.possible_newline();
+            }
+            context.reset_possible_newline();
+            gen_list_body_in(
+                body,
+                ";",
+                &fields.children,
+                context,
+                3,
+                FinalSeparator::MultiLine,
+                false,
+            )
+        }
+        None => body.take(),
+    };
+    items.extend(ir_helpers::with_indent_times(body, context.indent.block));
+
+    if space {
+        items.push_signal(Signal::SpaceIfNotTrailing);
+    }
+    items.possible_newline();
+    items.push_str(end);
+    context.expect_space_or_newline();
+
+    items.take()
+}
+
+fn is_pipe_operator(node: &Node) -> bool {
+    node.node_type == BinOp && node.original.trim() == "|>"
+}
+
+fn is_pipe_chain(node: &Node) -> bool {
+    flatten_exp_bin(node).iter().any(|n| is_pipe_operator(n))
+}
+
+/// Expression that only consists of a pipe chain
+fn is_pipe_exp(node: &Node) -> bool {
+    match node.node_type {
+        ExpBin => is_pipe_chain(node),
+        Exp | ExpNonVar | ExpNonDec => {
+            let children: Vec<&Node> = node.children.iter().filter(|n| !is_ignored(n)).collect();
+            children.len() == 1 && is_pipe_exp(children[0])
+        }
+        _ => false,
+    }
+}
+
+/// Operands and operators of nested binary expressions in source order
+/// Binary expressions break at the operators with the lowest precedence,
+/// e.g. `a * b +\n  c * d`
+fn gen_exp_bin(node: &Node, context: &mut Context) -> PrintItems {
+    let exp = match BinaryExp::from_nodes(&flatten_exp_bin(node)) {
+        Some(exp) => exp,
+        None => return gen_nodes(&node.children, context),
+    };
+    let mut items = PrintItems::new();
+    let lowest = exp.precedence().unwrap_or_default();
+    gen_binary(&exp, lowest, true, &mut items, context);
+    items
+}
+
+fn gen_binary(
+    exp: &BinaryExp,
+    lowest: u8,
+    breakable: bool,
+    items: &mut PrintItems,
+    context: &mut Context,
+) {
+    let (lhs, op, precedence, rhs) = match exp {
+        BinaryExp::Operand(n) => return items.extend(gen_node(n, context)),
+        BinaryExp::Op {
+            lhs,
+            op,
+            precedence,
+            rhs,
+        } => (lhs, op, *precedence, rhs),
+    };
+    // operators of the same precedence continue the chain, higher
+    // ones only break if their part doesn't fit on a line by itself
+    let line_width = context.config.line_width as usize;
+    let is_breakable = |exp: &BinaryExp| match exp.precedence() {
+        Some(p) if p == precedence => breakable,
+        _ => exp.width() > line_width,
+    };
+    let (lhs_breakable, rhs_breakable) = (is_breakable(lhs), is_breakable(rhs));
+
+    gen_binary(lhs, lowest, lhs_breakable, items, context);
+    let tight = context.config.operator_spacing == OperatorSpacing::Grouped
+        && precedence > lowest
+        && precedence >= ADDITIVE
+        && precedence != SHIFT
+        && is_simple_operand(lhs.last())
+        && is_simple_operand(rhs.first());
+    if tight {
+        items.extend(gen_id_no_space(op, context));
+    } else {
+        if breakable {
+            context.possible_newline();
+        }
+        items.extend(gen_keyword(op, context));
+    }
+    gen_binary(rhs, lowest, rhs_breakable, items, context);
+}
+
+/// Pipe chains, e.g. `x |> f _ |> g(_, 1)`, get one stage per line if they don't fit
+fn gen_pipe_chain(node: &Node, context: &mut Context) -> PrintItems {
+    let width = node
+        .original
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    let mut items = MultiLineGroup::new_max_width(
+        width.chars().count() as u32,
+        context.config.line_width,
+        context.indent.continuation,
+        true,
+        "pipe_chain",
+    );
+    for n in flatten_exp_bin(node) {
+        if is_pipe_operator(n) {
+            context.reset_expect();
+            context.expect_space();
+            items.possible_newline();
+            items.extend(gen_keyword(n, context));
+        } else {
+            items.extend(gen_node(n, context));
+        }
+    }
+    items.take()
+}
+
 fn gen_list(
     start: &str,
     sep: &str,
@@ -667,9 +1401,13 @@ fn gen_list(
     // if at least `space` not_ignored nodes are in `nodes`
     space: usize,
 ) -> PrintItems {
-    let mut items = MultiLineGroup::new(force_multiline, 0, false, "gen_list");
-
     let count = count_not_ignored_or_comment(nodes);
+    if count == 0 && nodes.iter().any(is_dangling_comment) {
+        return gen_dangling_comments(start, sep, end, nodes, context);
+    }
+
+    let mut items = MultiLineGroup::new(force_multiline, 0, false, "gen_list").with_reevaluation();
+
     // no newlines if list is empty or contains a single parenthesized child
     let no_newlines = count == 0
         || (can_condense
@@ -681,6 +1419,12 @@ fn gen_list(
     items.extend(context.gen_expected_space());
 
     items.push_str(start);
+    // `{a = 1}` instead of `{ a = 1 }`
+    let space = if start == "{" && !context.config.bracket_spacing {
+        usize::MAX
+    } else {
+        space
+    };
 
     //if count > 0 {
     //    items.extend(format!(" items: {} ", count).into());
@@ -696,19 +1440,32 @@ fn gen_list(
         context.force_space_or_newline();
     }
 
-    let body = gen_list_body(sep, nodes, context, force_multiline, 3, no_newlines);
+    let config = context.config;
+    let final_separator = match (sep, config.trailing_commas, config.semicolons) {
+        _ if no_newlines => FinalSeparator::Never,
+        // `(b + c,)` would be a tuple, `<Nat,>` and `f(x,)` look like one
+        (",", _, _) if count == 1 && matches!(start, "(" | "<") => FinalSeparator::Never,
+        (",", TrailingCommas::Never, _) | (";", _, Semicolons::Never) => FinalSeparator::Never,
+        (",", TrailingCommas::Always, _) | (";", _, Semicolons::Always) => FinalSeparator::Always,
+        // the brackets and items are on their own lines
+        _ => FinalSeparator::MultiLine,
+    };
+    // the items are on their own lines if the brackets are
+    let body = MultiLineGroup::new_in(&items, "gen_list_body");
+    let body = gen_list_body_in(body, sep, nodes, context, 3, final_separator, false);
     if no_newlines {
         items.extend(body);
     } else {
-        items.extend(ir_helpers::with_indent(body));
+        items.extend(ir_helpers::with_indent_times(body, context.indent.block));
     }
 
     if count >= space {
         items.push_signal(Signal::SpaceIfNotTrailing);
     }
 
+    // the closing bracket only goes on its own line with the items
     if !no_newlines {
-        items.possible_newline();
+        items.if_multiline(Signal::NewLine.into());
     }
     items.push_str(end);
     context.expect_space_or_newline();
@@ -716,15 +1473,79 @@ fn gen_list(
     items.take()
 }
 
+/// Comments inside empty brackets, on their own lines if any of them was on its own line:
+/// `{ /* empty */ }` or `(/* none */)`
+fn gen_dangling_comments(
+    start: &str,
+    sep: &str,
+    end: &str,
+    nodes: &Vec<Node>,
+    context: &mut Context,
+) -> PrintItems {
+    let mut items = PrintItems::new();
+    items.extend(context.gen_expected_space());
+    items.push_str(start);
+
+    let never = FinalSeparator::Never;
+    if nodes.iter().any(|n| n.original.contains('\n')) {
+        items.push_signal(Signal::NewLine);
+        let body = gen_list_body(sep, nodes, context, true, 2, never, false);
+        items.extend(ir_helpers::with_indent_times(body, context.indent.block));
+        items.push_signal(Signal::NewLine);
+    } else {
+        let space = start == "{" && context.config.bracket_spacing;
+        if !space {
+            context.no_space_before_comment();
+        }
+        items.extend(gen_list_body(sep, nodes, context, false, 0, never, false));
+        if space {
+            items.push_signal(Signal::SpaceIfNotTrailing);
+        }
+    }
+    items.push_str(end);
+    context.expect_space_or_newline();
+    items
+}
+
 fn gen_list_body(
     sep: &str,
     nodes: &Vec<Node>,
     context: &mut Context,
     force_multiline: bool,
     keep_newlines: usize,
-    omit_final_separator: bool,
+    final_separator: FinalSeparator,
+    // don't end an ignore range at the end of the list
+    keep_ignore_range: bool,
+) -> PrintItems {
+    let items = MultiLineGroup::new(force_multiline, 0, false, "gen_list_body");
+    gen_list_body_in(
+        items,
+        sep,
+        nodes,
+        context,
+        keep_newlines,
+        final_separator,
+        keep_ignore_range,
+    )
+}
+
+/// Items of a list added to `items`, they are on their own lines if the group is multi line
+fn gen_list_body_in(
+    mut items: MultiLineGroup,
+    sep: &str,
+    nodes: &Vec<Node>,
+    context: &mut Context,
+    keep_newlines: usize,
+    final_separator: FinalSeparator,
+    keep_ignore_range: bool,
 ) -> PrintItems {
-    let mut items = MultiLineGroup::new(force_multiline, 0, false, "gen_list_body");
+    let outer_comment_group = match context.config.trailing_comment_spacing {
+        TrailingCommentSpacing::AlignInGroup => Some(context.start_comment_group()),
+        _ => None,
+    };
+    // an ignore directive only applies to the next item of the same list,
+    // one before the list applies to its first item
+    let mut ignore_next = context.take_ignore_next();
 
     let count = count_not_ignored_or_comment(nodes);
     let mut need_separator = false;
@@ -732,9 +1553,31 @@ fn gen_list_body(
     let mut counter = 0; // count not_ignored nodes that have been printed
     let mut lines = 0;
     let mut allow_newlines = false; // allow newlines after comments or statements
+    let mut last_skipped = false; // last not_ignored node was kept as it is
+    let mut after_doc_comment = false; // no blank line between a doc comment and its declaration
 
     for (i, n) in nodes.iter().enumerate() {
         index = i;
+        if context.is_ignoring_range() {
+            if is_comment(n) && has_directive(n, &context.config.ignore_end_directive) {
+                if let Some((start, end)) = context.finish_ignore_range() {
+                    items.extend(gen_verbatim(start, end, context));
+                    if !context.text[start..end].trim_end().ends_with(sep) {
+                        items.push_str(sep);
+                    }
+                    items.push_signal(Signal::NewLine);
+                }
+            } else {
+                if !is_whitespace(n) {
+                    context.skip_ignored(n.start, n.end);
+                }
+                if !is_ignored(n) && !is_comment(n) {
+                    counter += 1;
+                    last_skipped = true;
+                }
+                continue;
+            }
+        }
         if is_whitespace(n) {
             lines = count_newlines(&n.original).clamp(0, keep_newlines);
         } else if is_ignored(n) {
@@ -750,7 +1593,9 @@ fn gen_list_body(
             }
             allow_newlines = true;
             lines = 0;
+            after_doc_comment = ends_with_doc_comment(n);
             items.extend(gen_node(n, context));
+            ignore_next |= context.take_ignore_next();
         } else {
             if need_separator {
                 items.push_str(sep);
@@ -758,18 +1603,30 @@ fn gen_list_body(
             }
             if allow_newlines {
                 if lines > 0 {
-                    items.extend(gen_newlines(lines));
+                    items.extend(gen_newlines(if after_doc_comment { 1 } else { lines }));
                     context.reset_expect();
                 } else {
                     items.possible_newline();
                 }
             }
             allow_newlines = true;
+            after_doc_comment = false;
             lines = 0;
-            items.extend(gen_node(n, context));
+            counter += 1;
+            if std::mem::take(&mut ignore_next) {
+                if is_list_node(n) {
+                    // e.g. the declarations of a block
+                    context.ignore_next();
+                    items.extend(gen_node(n, context));
+                } else {
+                    items.extend(gen_unformatted(n, context));
+                }
+            } else {
+                items.extend(gen_node(n, context));
+            }
             need_separator = true;
+            last_skipped = false;
 
-            counter += 1;
             if counter >= count {
                 // last not_ignored node was just generated
                 break;
@@ -777,11 +1634,23 @@ fn gen_list_body(
         }
     }
 
-    if count > 0 && !omit_final_separator {
-        items.if_multiline(sep.to_string().into());
+    // without an end directive, the ignore range ends with the list
+    if context.is_ignoring_range() && !keep_ignore_range {
+        if let Some((start, end)) = context.finish_ignore_range() {
+            items.extend(gen_verbatim(start, end, context));
+        }
+    }
+
+    if count > 0 && !last_skipped {
+        match final_separator {
+            FinalSeparator::Never => {}
+            FinalSeparator::Always => items.push_str(sep),
+            FinalSeparator::MultiLine => items.if_multiline(sep.to_string().into()),
+        }
     }
 
     let mut lines = 0;
+    // comments after the last node go after the final separator
     for n in nodes.iter().skip(index).skip(1) {
         match n.node_type {
             WHITESPACE => {
@@ -796,6 +1665,16 @@ fn gen_list_body(
         }
     }
 
+    if let Some(outer) = outer_comment_group {
+        if let Some(group) = context.finish_comment_group(outer) {
+            items.extend(group.gen_reevaluations());
+        }
+    }
+    // a directive after the last import applies to the first declaration
+    ignore_next |= context.take_ignore_next();
+    if ignore_next && keep_ignore_range {
+        context.ignore_next();
+    }
     items.take()
 }
 
@@ -847,7 +1726,23 @@ fn gen_spaced_comment(node: &Node, context: &mut Context) -> PrintItems {
 
 fn gen_exp_un(node: &Node, context: &mut Context) -> PrintItems {
     let items = if node.has_child(&HashTag) {
-        gen_nodes_no_space_between(&node.children, context)
+        // `#tag(x)` stays attached, but `#tag x` needs the space to keep its meaning
+        let mut items = PrintItems::new();
+        for n in node.children.iter().filter(|n| !is_ignored(n)) {
+            match n.node_type {
+                ExpNullary if !n.original.trim_start().starts_with('(') => {
+                    context.force_space();
+                    items.extend(gen_node(n, context));
+                }
+                _ => {
+                    if n.start > node.start {
+                        context.reset_expect();
+                    }
+                    items.extend(gen_node(n, context));
+                }
+            }
+        }
+        items
     } else {
         gen_nodes(&node.children, context)
     };
@@ -857,37 +1752,36 @@ fn gen_exp_un(node: &Node, context: &mut Context) -> PrintItems {
 }
 
 fn gen_exp_non_dec(node: &Node, context: &mut Context) -> PrintItems {
-    if node.has_child(&KeywordSwitch) {
-        return gen_switch(node, context);
-    }
-    if node.has_child(&KeywordIf) {
-        return gen_if_statement(node, context);
-    }
-    if node.has_child(&KeywordLabel) {
-        return gen_label(node, context);
+    let kind = ast::ExpKind::of(node);
+    match kind {
+        ast::ExpKind::Switch => return gen_switch(node, context),
+        ast::ExpKind::If => return gen_if_statement(node, context),
+        ast::ExpKind::Label => return gen_label(node, context),
+        _ => {}
     }
     let force_multiline = count_newlines(&node.original) > 0;
     let mut items = MultiLineGroup::new(force_multiline, 0, true, "exp_non_dec");
-    let is_for_loop = node.has_child(&KeywordFor);
+    let is_for_loop = kind == ast::ExpKind::For;
+    let continuation = context.indent.continuation;
     let mut indent = false;
     for n in node.children.iter() {
         match n.node_type {
             KeywordReturn => {
                 items.extend(gen_node(n, context));
                 if !indent {
-                    items.push_signal(Signal::StartIndent);
+                    items.extend(signal_times(Signal::StartIndent, continuation));
                     indent = true;
                 }
             }
             ColonEqual | BinAssign => {
                 items.extend(gen_id(n, context));
                 context.force_space_or_newline();
-                items.push_signal(Signal::StartIndent);
+                items.extend(signal_times(Signal::StartIndent, continuation));
                 indent = true;
             }
             Exp => {
-                if indent && n.starts_with(&CurlyBracketOpen) {
-                    items.push_signal(Signal::FinishIndent);
+                if indent && (n.starts_with(&CurlyBracketOpen) || is_pipe_exp(n)) {
+                    items.extend(signal_times(Signal::FinishIndent, continuation));
                     indent = false;
                 }
                 items.extend(gen_node(n, context));
@@ -897,7 +1791,7 @@ fn gen_exp_non_dec(node: &Node, context: &mut Context) -> PrintItems {
                     items.push_str(")");
                 }
                 if indent {
-                    items.push_signal(Signal::FinishIndent);
+                    items.extend(signal_times(Signal::FinishIndent, continuation));
                     indent = false;
                 }
                 items.extend(gen_node(n, context));
@@ -914,7 +1808,7 @@ fn gen_exp_non_dec(node: &Node, context: &mut Context) -> PrintItems {
     }
 
     if indent {
-        items.push_signal(Signal::FinishIndent);
+        items.extend(signal_times(Signal::FinishIndent, continuation));
     }
 
     items.take()
@@ -958,7 +1852,8 @@ fn gen_if_statement(node: &Node, context: &mut Context) -> PrintItems {
                 if n.has_child(&Block) || n.starts_with(&KeywordIf) {
                     items.extend(gen_node(&n, context))
                 } else {
-                    items.extend(ir_helpers::with_indent(gen_node(&n, context)))
+                    let block = context.indent.block;
+                    items.extend(ir_helpers::with_indent_times(gen_node(&n, context), block))
                 }
             }
             _ => items.extend(gen_node(&n, context)),
@@ -1068,12 +1963,13 @@ fn gen_func_body(node: &Node, context: &mut Context) -> PrintItems {
     let mut items = MultiLineGroup::new(force_multiline, 0, false, "func_body");
 
     items.push_signal(Signal::StartNewLineGroup);
+    let continuation = context.indent.continuation;
     let mut indent = false;
     // child is either Exp or Block
     if node.has_child(&Exp) {
         // single expression
         context.expect_space();
-        items.push_signal(Signal::QueueStartIndent);
+        items.extend(signal_times(Signal::QueueStartIndent, continuation));
         indent = true;
         items.extend(context.gen_expected_space());
         items.push_str("=");
@@ -1085,7 +1981,7 @@ fn gen_func_body(node: &Node, context: &mut Context) -> PrintItems {
     }
     items.extend(gen_nodes(&node.children, context));
     if indent {
-        items.push_signal(Signal::FinishIndent);
+        items.extend(signal_times(Signal::FinishIndent, continuation));
     }
     items.push_signal(Signal::FinishNewLineGroup);
 
@@ -1,5 +1,7 @@
-use crate::motoko_parser::{Node, NodeType};
+use super::comments::doc_block_lines;
+use crate::motoko_parser::{Attachment, Node, NodeType};
 use dprint_core::formatting::*;
+use std::cell::RefCell;
 use std::rc::Rc;
 
 pub fn count_newlines(s: &String) -> usize {
@@ -35,6 +37,55 @@ pub fn is_comment(node: &Node) -> bool {
     }
 }
 
+/// Lists that are items of an outer list, e.g. the declarations of a block
+pub fn is_list_node(node: &Node) -> bool {
+    matches!(
+        node.node_type,
+        NodeType::DeclarationList | NodeType::ImportList | NodeType::ExpObjFields
+    )
+}
+
+/// Whether a comment is inside brackets or a list without any items
+pub fn is_dangling_comment(node: &Node) -> bool {
+    node.attachment == Some(Attachment::Dangling)
+}
+
+/// Whether a comment follows code on its line
+pub fn is_trailing_comment(node: &Node) -> bool {
+    node.attachment == Some(Attachment::Trailing)
+}
+
+/// Whether the last comment of a group of comments is a doc comment, `///` or `/** … */`
+pub fn ends_with_doc_comment(node: &Node) -> bool {
+    match node.node_type {
+        NodeType::DocComment => true,
+        NodeType::BlockComment => doc_block_lines(node.original.trim()).is_some(),
+        NodeType::LineComment => false,
+        _ => node
+            .children
+            .iter()
+            .rev()
+            .find(|n| !is_whitespace(n))
+            .is_some_and(ends_with_doc_comment),
+    }
+}
+
+/// Whether a line or block comment consists of the directive only, e.g. `// motoko-fmt-ignore`
+pub fn is_directive(node: &Node, directive: &str) -> bool {
+    let text = node.original.trim();
+    let content = match node.node_type {
+        NodeType::LineComment => text.strip_prefix("//"),
+        NodeType::BlockComment => text.strip_prefix("/*").and_then(|t| t.strip_suffix("*/")),
+        _ => None,
+    };
+    content.is_some_and(|c| c.trim() == directive)
+}
+
+/// Whether any comment inside a group of comments is the directive
+pub fn has_directive(node: &Node, directive: &str) -> bool {
+    is_directive(node, directive) || node.children.iter().any(|n| has_directive(n, directive))
+}
+
 pub fn is_ignored(node: &Node) -> bool {
     match node.node_type {
         NodeType::WHITESPACE => true,
@@ -81,6 +132,15 @@ pub fn if_not_start_of_line(then: PrintItems) -> PrintItems {
     items
 }
 
+/// `times` indentation signals of the same kind, e.g. `Signal::StartIndent`
+pub fn signal_times(signal: Signal, times: u32) -> PrintItems {
+    let mut items = PrintItems::new();
+    for _ in 0..times {
+        items.push_signal(signal);
+    }
+    items
+}
+
 pub fn with_queued_indent_times(items: PrintItems, times: u32) -> PrintItems {
     let mut items = items;
     for _ in 0..times {
@@ -89,6 +149,15 @@ pub fn with_queued_indent_times(items: PrintItems, times: u32) -> PrintItems {
     items
 }
 
+/// Whether the last item of a list is followed by a separator
+#[derive(Clone)]
+pub enum FinalSeparator {
+    Never,
+    Always,
+    /// If the items of the list are on multiple lines
+    MultiLine,
+}
+
 /// Group of optional linebreaks that break all or none
 pub struct MultiLineGroup {
     resolver: ConditionResolver,
@@ -98,6 +167,9 @@ pub struct MultiLineGroup {
     queue_indent: bool,
     items: PrintItems,
     _info: String,
+    /// Conditions to check again at the end of the outermost group that shares them
+    reevaluations: Option<Rc<RefCell<Vec<ConditionReevaluation>>>>,
+    shared: bool,
 }
 
 impl MultiLineGroup {
@@ -106,20 +178,50 @@ impl MultiLineGroup {
         indent: u32,
         queue_indent: bool,
         info: &'static str,
+    ) -> Self {
+        Self::new_with_width(force_multi_line, None, indent, queue_indent, info)
+    }
+
+    /// Group that is also multi line if `width` columns don't fit into `max_width`
+    /// from where the group starts
+    pub fn new_max_width(
+        width: u32,
+        max_width: u32,
+        indent: u32,
+        queue_indent: bool,
+        info: &'static str,
+    ) -> Self {
+        Self::new_with_width(false, Some((width, max_width)), indent, queue_indent, info)
+    }
+
+    fn new_with_width(
+        force_multi_line: bool,
+        width: Option<(u32, u32)>,
+        indent: u32,
+        queue_indent: bool,
+        info: &'static str,
     ) -> Self {
         let start_ln = LineNumber::new(info);
+        let start_column = ColumnNumber::new(info);
         let end_ln = LineNumber::new(info);
         let _ = LineNumber::new("unused");
         let resolver = Rc::new(move |condition_context: &mut ConditionResolverContext| {
             if force_multi_line {
                 return Some(true);
             }
+            if let Some((width, max_width)) = width {
+                let column = condition_context.resolved_column_number(start_column)?;
+                if column + width > max_width {
+                    return Some(true);
+                }
+            }
             // check if it spans multiple lines, and if it does then make it multi-line
             condition_helpers::is_multiple_lines(condition_context, start_ln, end_ln)
         });
 
         let mut items = PrintItems::new();
         items.push_info(start_ln);
+        items.push_info(start_column);
         items.push_anchor(LineNumberAnchor::new(start_ln));
         items.push_signal(Signal::StartNewLineGroup);
 
@@ -131,9 +233,32 @@ impl MultiLineGroup {
             queue_indent,
             items,
             _info: info.into(),
+            reevaluations: None,
+            shared: false,
         }
     }
 
+    /// Check the conditions of the group again at its end, so that all of its linebreaks
+    /// agree even if the lines before the group changed after they were printed
+    pub fn with_reevaluation(mut self) -> Self {
+        self.reevaluations = Some(Default::default());
+        self
+    }
+
+    /// Group that is multi line together with `outer`, e.g. the items of a list and its brackets
+    pub fn new_in(outer: &MultiLineGroup, info: &'static str) -> Self {
+        let mut group = Self::new(false, 0, false, info);
+        group.resolver = outer.resolver();
+        group.reevaluations = outer.reevaluations.clone();
+        group.shared = true;
+        group
+    }
+
+    /// Condition that is true if the group is multi line
+    pub fn resolver(&self) -> ConditionResolver {
+        self.resolver.clone()
+    }
+
     pub fn extend(&mut self, items: PrintItems) {
         self.items.extend(items);
     }
@@ -150,6 +275,11 @@ impl MultiLineGroup {
         self.items.push_info(self.end_ln);
         self.items.push_anchor(LineNumberAnchor::new(self.end_ln));
         self.items.push_signal(Signal::FinishNewLineGroup);
+        if let Some(reevaluations) = self.reevaluations.as_ref().filter(|_| !self.shared) {
+            for reevaluation in reevaluations.borrow_mut().drain(..) {
+                self.items.push_reevaluation(reevaluation);
+            }
+        }
 
         let rc_path = self.items.into_rc_path();
 
@@ -175,9 +305,18 @@ impl MultiLineGroup {
     }
 
     pub fn if_multiline_or(&mut self, multi: PrintItems, single: PrintItems) {
-        let cond =
-            conditions::if_true_or("multi_line_group", self.resolver.clone(), multi, single).into();
-        self.items.extend(cond);
+        let cond = conditions::if_true_or("multi_line_group", self.resolver.clone(), multi, single);
+        self.push_condition(cond);
+    }
+
+    /// Condition that depends on whether the group is multi line
+    pub fn push_condition(&mut self, mut condition: Condition) {
+        if let Some(reevaluations) = &self.reevaluations {
+            reevaluations
+                .borrow_mut()
+                .push(condition.create_reevaluation());
+        }
+        self.items.push_condition(condition);
     }
 
     pub fn if_multiline(&mut self, multi: PrintItems) {
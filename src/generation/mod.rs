@@ -1,5 +1,10 @@
+pub(crate) mod binary;
+mod comments;
 mod context;
 mod generate;
 mod helper;
+mod numbers;
 
+pub use context::Indentation;
 pub use generate::*;
+pub(crate) use helper::{has_directive, is_comment, is_list_node};
@@ -0,0 +1,107 @@
+use crate::configuration::NumberSeparators;
+
+/// Normalize a number literal: lowercase exponent markers, and add, remove or
+/// keep the `_` digit separators
+pub fn format_number(text: &str, mode: NumberSeparators) -> String {
+    if mode == NumberSeparators::Off {
+        return text.to_string();
+    }
+
+    let (prefix, rest, group, exponent_markers) = match text.strip_prefix("0x") {
+        Some(rest) => ("0x", rest, 4, ['p', 'P']),
+        None => ("", text, 3, ['e', 'E']),
+    };
+    let (mantissa, exponent) = match rest.find(exponent_markers) {
+        Some(i) => (&rest[..i], Some(&rest[i + 1..])),
+        None => (rest, None),
+    };
+    let (int, frac) = match mantissa.split_once('.') {
+        Some((int, frac)) => (int, Some(frac)),
+        None => (mantissa, None),
+    };
+
+    let mut result = prefix.to_string();
+    result.push_str(&digits(int, mode, group, true));
+    if let Some(frac) = frac {
+        result.push('.');
+        result.push_str(&digits(frac, mode, group, false));
+    }
+    if let Some(exponent) = exponent {
+        result.push(exponent_markers[0]);
+        result.push_str(&digits(exponent, mode, 3, true));
+    }
+    result
+}
+
+fn digits(digits: &str, mode: NumberSeparators, group: usize, from_right: bool) -> String {
+    let plain = digits.replace('_', "");
+    match mode {
+        NumberSeparators::Off | NumberSeparators::Preserve => digits.to_string(),
+        NumberSeparators::Remove => plain,
+        NumberSeparators::Add => {
+            // short numbers like `1000` or `0xff` are easy enough to read
+            let unsigned = plain.trim_start_matches('-');
+            if unsigned.len() <= 4 {
+                return plain;
+            }
+            let sign = &plain[..plain.len() - unsigned.len()];
+            let mut unsigned = unsigned.to_string();
+            if from_right && group == 4 {
+                // hex numbers are padded to full groups: `0x0100_0000`
+                let padding = (group - unsigned.len() % group) % group;
+                unsigned.insert_str(0, &"0".repeat(padding));
+            }
+            let chars: Vec<char> = unsigned.chars().collect();
+            let first = if from_right {
+                match chars.len() % group {
+                    0 => group,
+                    n => n,
+                }
+            } else {
+                group
+            };
+            let mut result = sign.to_string();
+            for (i, c) in chars.iter().enumerate() {
+                if i > 0 && i >= first && (i - first) % group == 0 {
+                    result.push('_');
+                }
+                result.push(*c);
+            }
+            result
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_numbers {
+    use super::*;
+    use NumberSeparators::*;
+
+    #[test]
+    fn test_add_separators() {
+        assert_eq!(format_number("1000", Add), "1000");
+        assert_eq!(format_number("10000", Add), "10_000");
+        assert_eq!(format_number("1000000", Add), "1_000_000");
+        assert_eq!(format_number("100000000000000", Add), "100_000_000_000_000");
+        assert_eq!(format_number("10_00_000", Add), "1_000_000");
+        assert_eq!(format_number("0x1000000", Add), "0x0100_0000");
+        assert_eq!(format_number("0x1234567", Add), "0x0123_4567");
+        assert_eq!(format_number("0xff", Add), "0xff");
+        assert_eq!(format_number("1234567.1234567", Add), "1_234_567.123_456_7");
+        assert_eq!(format_number("1.5E10", Add), "1.5e10");
+    }
+
+    #[test]
+    fn test_remove_separators() {
+        assert_eq!(format_number("1_000_000", Remove), "1000000");
+        assert_eq!(format_number("0x01_00", Remove), "0x0100");
+        assert_eq!(format_number("1_0.0_1e-1_0", Remove), "10.01e-10");
+    }
+
+    #[test]
+    fn test_preserve_and_off() {
+        assert_eq!(format_number("10_00", Preserve), "10_00");
+        assert_eq!(format_number("0xFF_FFP1", Preserve), "0xFF_FFp1");
+        assert_eq!(format_number("0xFF_FFP1", Off), "0xFF_FFP1");
+    }
+}
@@ -1,4 +1,5 @@
 pub mod configuration;
+mod format_range;
 mod format_text;
 mod generation;
 mod motoko_parser;
@@ -6,7 +7,10 @@ mod motoko_parser;
 #[macro_use]
 extern crate pest_derive;
 
-pub use format_text::format_text;
+pub use format_range::{format_range, TextEdit};
+pub use format_text::{format_text, format_text_with_diagnostics, FormatTextResult};
+pub use motoko_parser::ast;
+pub use motoko_parser::Diagnostic;
 
 #[cfg(feature = "tracing")]
 pub use format_text::trace_file;
@@ -0,0 +1,2413 @@
+//! Typed Motoko AST
+//!
+//! The pest grammar produces a lossless tree of [`Node`]s that also contains
+//! whitespace, comments and punctuation. This module converts that tree into
+//! typed declarations, expressions, patterns and types with named fields.
+//! Every item keeps the byte [`Span`] of its source text.
+use super::parser::{Node, NodeType, NodeType as N, NodeType::*};
+use crate::generation::binary::{group_by_precedence, precedence};
+
+/// Byte range of an item in the source text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ident {
+    pub name: String,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LitKind {
+    Nat,
+    Float,
+    Char,
+    Text,
+    Bool,
+    Null,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lit {
+    pub kind: LitKind,
+    pub text: String,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+    pub imports: Vec<Import>,
+    pub decs: Vec<Dec>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Import {
+    pub pat: Pat,
+    /// Import path without the surrounding quotes, e.g. `mo:base/Array`
+    pub url: String,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObjSort {
+    Object,
+    Actor,
+    Module,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FuncSort {
+    Shared,
+    SharedQuery,
+    SharedCompositeQuery,
+    Query,
+    CompositeQuery,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Visibility {
+    Public,
+    Private,
+    System,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stability {
+    Flexible,
+    Stable,
+    Transient,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Dec {
+    Exp {
+        exp: Exp,
+    },
+    Var {
+        id: Ident,
+        typ: Option<Typ>,
+        exp: Exp,
+        span: Span,
+    },
+    Let {
+        pat: Pat,
+        exp: Exp,
+        /// `let <pat> = <exp> else <else_>`
+        else_: Option<Box<Exp>>,
+        span: Span,
+    },
+    Type {
+        id: Ident,
+        binds: Vec<TypBind>,
+        typ: Typ,
+        span: Span,
+    },
+    Obj {
+        /// `(with migration = …) actor`
+        parenthetical: Option<Parenthetical>,
+        /// `persistent actor`
+        persistent: bool,
+        sort: ObjSort,
+        id: Option<Ident>,
+        fields: Vec<DecField>,
+        span: Span,
+    },
+    Func {
+        shared: Option<SharedPat>,
+        id: Option<Ident>,
+        binds: Vec<TypBind>,
+        param: Pat,
+        result: Option<Typ>,
+        body: Box<Exp>,
+        span: Span,
+    },
+    Class {
+        shared: Option<SharedPat>,
+        parenthetical: Option<Parenthetical>,
+        persistent: bool,
+        sort: Option<ObjSort>,
+        id: Option<Ident>,
+        binds: Vec<TypBind>,
+        param: Pat,
+        result: Option<Typ>,
+        this: Option<Ident>,
+        fields: Vec<DecField>,
+        span: Span,
+    },
+}
+
+/// `shared`/`query` modifier of a function or class, e.g. `shared(msg) query`
+#[derive(Debug, Clone, PartialEq)]
+pub struct SharedPat {
+    pub sort: FuncSort,
+    pub pat: Option<Pat>,
+    pub span: Span,
+}
+
+/// Declaration inside an object, actor or module body
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecField {
+    pub visibility: Option<Visibility>,
+    pub stability: Option<Stability>,
+    pub dec: Dec,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Exp {
+    Lit {
+        lit: Lit,
+    },
+    Var {
+        id: Ident,
+    },
+    Dec {
+        dec: Box<Dec>,
+    },
+    Block {
+        decs: Vec<Dec>,
+        span: Span,
+    },
+    /// Object literal, `bases` are the objects merged with `and` and extended `with` the fields
+    Obj {
+        bases: Vec<Exp>,
+        fields: Vec<ExpField>,
+        span: Span,
+    },
+    Paren {
+        exp: Box<Exp>,
+        span: Span,
+    },
+    Tuple {
+        exps: Vec<Exp>,
+        span: Span,
+    },
+    Array {
+        mutable: bool,
+        exps: Vec<Exp>,
+        span: Span,
+    },
+    Call {
+        func: Box<Exp>,
+        /// `system` capability passed as first type argument: `f<system>()`
+        system: bool,
+        type_args: Vec<Typ>,
+        arg: Box<Exp>,
+        span: Span,
+    },
+    Dot {
+        exp: Box<Exp>,
+        field: Ident,
+        span: Span,
+    },
+    Proj {
+        exp: Box<Exp>,
+        index: String,
+        span: Span,
+    },
+    Index {
+        exp: Box<Exp>,
+        index: Box<Exp>,
+        span: Span,
+    },
+    Bang {
+        exp: Box<Exp>,
+        span: Span,
+    },
+    Variant {
+        tag: Ident,
+        exp: Option<Box<Exp>>,
+        span: Span,
+    },
+    Opt {
+        exp: Box<Exp>,
+        span: Span,
+    },
+    /// Prefix operators `+`, `-`, `^` and the unary assignments `+=`, `-=`, `^=`
+    Unary {
+        op: String,
+        exp: Box<Exp>,
+        span: Span,
+    },
+    Not {
+        exp: Box<Exp>,
+        span: Span,
+    },
+    DebugShow {
+        exp: Box<Exp>,
+        span: Span,
+    },
+    ToCandid {
+        exps: Vec<Exp>,
+        span: Span,
+    },
+    FromCandid {
+        exp: Box<Exp>,
+        span: Span,
+    },
+    Actor {
+        exp: Box<Exp>,
+        span: Span,
+    },
+    /// Binary and relational operators including `and` and `or`
+    Binary {
+        lhs: Box<Exp>,
+        op: String,
+        rhs: Box<Exp>,
+        span: Span,
+    },
+    Annot {
+        exp: Box<Exp>,
+        typ: Typ,
+        span: Span,
+    },
+    /// `:=` and compound assignments like `+=`
+    Assign {
+        lhs: Box<Exp>,
+        op: String,
+        rhs: Box<Exp>,
+        span: Span,
+    },
+    If {
+        cond: Box<Exp>,
+        then: Box<Exp>,
+        else_: Option<Box<Exp>>,
+        span: Span,
+    },
+    Switch {
+        exp: Box<Exp>,
+        cases: Vec<Case>,
+        span: Span,
+    },
+    While {
+        cond: Box<Exp>,
+        body: Box<Exp>,
+        span: Span,
+    },
+    Loop {
+        body: Box<Exp>,
+        cond: Option<Box<Exp>>,
+        span: Span,
+    },
+    For {
+        pat: Pat,
+        iter: Box<Exp>,
+        body: Box<Exp>,
+        span: Span,
+    },
+    Label {
+        id: Ident,
+        typ: Option<Typ>,
+        body: Box<Exp>,
+        span: Span,
+    },
+    Break {
+        id: Ident,
+        exp: Option<Box<Exp>>,
+        span: Span,
+    },
+    Continue {
+        id: Ident,
+        span: Span,
+    },
+    Return {
+        exp: Option<Box<Exp>>,
+        span: Span,
+    },
+    /// `async e` or `async* e`
+    Async {
+        star: bool,
+        exp: Box<Exp>,
+        span: Span,
+    },
+    /// `await e` or `await* e`
+    Await {
+        star: bool,
+        exp: Box<Exp>,
+        span: Span,
+    },
+    Assert {
+        exp: Box<Exp>,
+        span: Span,
+    },
+    Debug {
+        exp: Box<Exp>,
+        span: Span,
+    },
+    Ignore {
+        exp: Box<Exp>,
+        span: Span,
+    },
+    Throw {
+        exp: Box<Exp>,
+        span: Span,
+    },
+    Try {
+        exp: Box<Exp>,
+        catch: Option<Box<Catch>>,
+        finally: Option<Box<Exp>>,
+        span: Span,
+    },
+    Do {
+        optional: bool,
+        block: Box<Exp>,
+        span: Span,
+    },
+    /// Call with attributes, e.g. `(with cycles = 1_000) f()`
+    Parenthetical {
+        parenthetical: Parenthetical,
+        exp: Box<Exp>,
+        span: Span,
+    },
+    /// `_` in the stage of a pipe expression
+    Placeholder {
+        span: Span,
+    },
+    /// Syntax that is accepted by the grammar but has no typed representation
+    Other {
+        span: Span,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExpField {
+    pub mutable: bool,
+    pub id: Ident,
+    pub typ: Option<Typ>,
+    pub exp: Option<Exp>,
+    pub span: Span,
+}
+
+/// Attributes like `(with cycles = 1_000)` or `(base with migration = m)`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Parenthetical {
+    pub base: Option<Box<Exp>>,
+    pub fields: Vec<ExpField>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Case {
+    pub pat: Pat,
+    pub exp: Exp,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Catch {
+    pub pat: Pat,
+    pub exp: Exp,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pat {
+    Wild {
+        span: Span,
+    },
+    Var {
+        id: Ident,
+    },
+    Lit {
+        lit: Lit,
+    },
+    Paren {
+        pat: Box<Pat>,
+        span: Span,
+    },
+    Tuple {
+        pats: Vec<Pat>,
+        span: Span,
+    },
+    Obj {
+        fields: Vec<PatField>,
+        span: Span,
+    },
+    Variant {
+        tag: Ident,
+        pat: Option<Box<Pat>>,
+        span: Span,
+    },
+    Opt {
+        pat: Box<Pat>,
+        span: Span,
+    },
+    /// Signed literal pattern like `-1`
+    Signed {
+        op: String,
+        lit: Lit,
+        span: Span,
+    },
+    Or {
+        lhs: Box<Pat>,
+        rhs: Box<Pat>,
+        span: Span,
+    },
+    Annot {
+        pat: Box<Pat>,
+        typ: Typ,
+        span: Span,
+    },
+    Other {
+        span: Span,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PatField {
+    pub id: Ident,
+    pub typ: Option<Typ>,
+    pub pat: Option<Pat>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Typ {
+    /// Named type like `Nat` or `List.List<T>`
+    Path {
+        path: Vec<Ident>,
+        args: Vec<Typ>,
+        span: Span,
+    },
+    /// `Null`, `None`, `Any`, `Error` and `actor`
+    Prim {
+        name: String,
+        span: Span,
+    },
+    Paren {
+        typ: Box<Typ>,
+        span: Span,
+    },
+    Tuple {
+        items: Vec<TypItem>,
+        span: Span,
+    },
+    Array {
+        mutable: bool,
+        elem: Box<Typ>,
+        span: Span,
+    },
+    Opt {
+        typ: Box<Typ>,
+        span: Span,
+    },
+    Variant {
+        tags: Vec<TypTag>,
+        span: Span,
+    },
+    Obj {
+        sort: Option<ObjSort>,
+        fields: Vec<TypField>,
+        span: Span,
+    },
+    Func {
+        sort: Option<FuncSort>,
+        binds: Vec<TypBind>,
+        arg: Box<Typ>,
+        result: Box<Typ>,
+        span: Span,
+    },
+    /// `async T` or `async* T`
+    Async {
+        star: bool,
+        typ: Box<Typ>,
+        span: Span,
+    },
+    And {
+        lhs: Box<Typ>,
+        rhs: Box<Typ>,
+        span: Span,
+    },
+    Or {
+        lhs: Box<Typ>,
+        rhs: Box<Typ>,
+        span: Span,
+    },
+    Other {
+        span: Span,
+    },
+}
+
+/// Tuple component, optionally named: `(name : Text, Nat)`
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypItem {
+    pub id: Option<Ident>,
+    pub typ: Typ,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypTag {
+    pub tag: Ident,
+    pub typ: Option<Typ>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypField {
+    pub mutable: bool,
+    pub id: Ident,
+    pub binds: Vec<TypBind>,
+    /// Parameter type of a function field: `f(Nat) : Nat`
+    pub param: Option<Typ>,
+    pub typ: Typ,
+    pub span: Span,
+}
+
+/// Type parameter with optional bound: `T <: Any`, or the `system` capability
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypBind {
+    pub id: Ident,
+    pub bound: Option<Typ>,
+    pub span: Span,
+}
+
+/// Parse a Motoko source file into a typed [`Program`]
+pub fn parse(content: &str) -> anyhow::Result<Program> {
+    let nodes =
+        super::parse(content).map_err(|err| super::Diagnostic::from_pest(None, content, &err))?;
+    let node = nodes
+        .first()
+        .ok_or_else(|| anyhow::anyhow!("parser returned no nodes"))?;
+    Ok(Program::from_node(node))
+}
+
+impl Span {
+    fn of(node: &Node) -> Self {
+        Span {
+            start: node.start,
+            end: content_end(node),
+        }
+    }
+
+    fn between(first: &Node, last: &Node) -> Self {
+        Span {
+            start: first.start,
+            end: content_end(last),
+        }
+    }
+}
+
+impl Ident {
+    fn from_node(node: &Node) -> Self {
+        Ident {
+            name: node.original.trim().to_string(),
+            span: Span::of(node),
+        }
+    }
+}
+
+impl Lit {
+    fn from_node(node: &Node) -> Self {
+        let kind = match node.node_type {
+            KeywordTrue | KeywordFalse => LitKind::Bool,
+            KeywordNull => LitKind::Null,
+            _ => match significant(node).first().map(|n| &n.node_type) {
+                Some(Float) => LitKind::Float,
+                Some(Char) => LitKind::Char,
+                Some(Text) => LitKind::Text,
+                _ => LitKind::Nat,
+            },
+        };
+        Lit {
+            kind,
+            text: node.original.trim().to_string(),
+            span: Span::of(node),
+        }
+    }
+}
+
+impl Program {
+    pub(crate) fn from_node(node: &Node) -> Self {
+        if node.node_type == Motoko {
+            if let Some(program) = node.children.iter().find(|n| n.node_type == N::Program) {
+                return Self::from_node(program);
+            }
+        }
+        let mut imports = vec![];
+        let mut decs = vec![];
+        for list in significant(node) {
+            match list.node_type {
+                ImportList => imports.extend(significant(list).into_iter().map(Import::from_node)),
+                DeclarationList => decs.extend(significant(list).into_iter().map(Dec::from_node)),
+                _ => {}
+            }
+        }
+        Program {
+            imports,
+            decs,
+            span: Span::of(node),
+        }
+    }
+}
+
+impl Import {
+    fn from_node(node: &Node) -> Self {
+        let mut pat = Pat::Other {
+            span: Span::of(node),
+        };
+        let mut url = String::new();
+        for n in significant(node) {
+            match n.node_type {
+                PatternNullary => pat = Pat::from_node(n),
+                Text => url = n.original.trim_matches('"').to_string(),
+                _ => {}
+            }
+        }
+        Import {
+            pat,
+            url,
+            span: Span::of(node),
+        }
+    }
+}
+
+/// Kind of a `DeclarationNonVar`, found from its direct children without converting them
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecKind {
+    Let,
+    Type,
+    Class,
+    Func,
+    Obj,
+    Other,
+}
+
+impl DecKind {
+    pub fn of(node: &Node) -> Self {
+        let has = |t: NodeType| node.children.iter().any(|n| n.node_type == t);
+        if has(KeywordLet) {
+            DecKind::Let
+        } else if has(KeywordType) {
+            DecKind::Type
+        } else if has(KeywordClass) {
+            DecKind::Class
+        } else if has(KeywordFunc) {
+            DecKind::Func
+        } else if has(N::ObjSort) {
+            DecKind::Obj
+        } else {
+            DecKind::Other
+        }
+    }
+}
+
+/// Kind of an `ExpNonDec`, given by its first child without converting the children
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExpKind {
+    /// Not keyword prefixed: binary expressions and assignments
+    Binary,
+    Assert,
+    Async,
+    Await,
+    Debug,
+    Ignore,
+    Throw,
+    Break,
+    Continue,
+    Do,
+    For,
+    If,
+    Label,
+    Loop,
+    Return,
+    Switch,
+    Try,
+    While,
+    Other,
+}
+
+impl ExpKind {
+    pub fn of(node: &Node) -> Self {
+        let first = node
+            .children
+            .iter()
+            .find(|n| !matches!(n.node_type, WHITESPACE | COMMENT | Comment));
+        match first.map(|n| &n.node_type) {
+            Some(ExpBin) => ExpKind::Binary,
+            Some(KeywordAssert) => ExpKind::Assert,
+            Some(KeywordAsync | KeywordAsyncStar) => ExpKind::Async,
+            Some(KeywordAwait | KeywordAwaitStar) => ExpKind::Await,
+            Some(KeywordDebug) => ExpKind::Debug,
+            Some(KeywordIgnore) => ExpKind::Ignore,
+            Some(KeywordThrow) => ExpKind::Throw,
+            Some(KeywordBreak) => ExpKind::Break,
+            Some(KeywordContinue) => ExpKind::Continue,
+            Some(KeywordDo) => ExpKind::Do,
+            Some(KeywordFor) => ExpKind::For,
+            Some(KeywordIf) => ExpKind::If,
+            Some(KeywordLabel) => ExpKind::Label,
+            Some(KeywordLoop) => ExpKind::Loop,
+            Some(KeywordReturn) => ExpKind::Return,
+            Some(KeywordSwitch) => ExpKind::Switch,
+            Some(KeywordTry) => ExpKind::Try,
+            Some(KeywordWhile) => ExpKind::While,
+            _ => ExpKind::Other,
+        }
+    }
+}
+
+impl Dec {
+    pub(crate) fn from_node(node: &Node) -> Self {
+        let children = significant(node);
+        match node.node_type {
+            Declaration => match children.first() {
+                Some(n) => Self::from_node(n),
+                None => Self::other(node),
+            },
+            DeclarationVar => {
+                let mut typ = None;
+                let mut exp = None;
+                for n in children.iter() {
+                    match n.node_type {
+                        Type => typ = Some(Typ::from_node(n)),
+                        N::Exp => exp = Some(Exp::from_node(n)),
+                        _ => {}
+                    }
+                }
+                Dec::Var {
+                    id: find_ident(&children, node),
+                    typ,
+                    exp: exp.unwrap_or_else(|| Exp::other(node)),
+                    span: Span::of(node),
+                }
+            }
+            DeclarationNonVar => Self::from_non_var(node, &children),
+            ExpNonDec | KeywordTrue | KeywordFalse | KeywordNull => Dec::Exp {
+                exp: Exp::from_node(node),
+            },
+            _ => Self::other(node),
+        }
+    }
+
+    fn from_non_var(node: &Node, children: &[&Node]) -> Self {
+        let span = Span::of(node);
+        let has = |t: NodeType| children.iter().any(|n| n.node_type == t);
+        let kind = DecKind::of(node);
+
+        if kind == DecKind::Let {
+            return Dec::Let {
+                pat: find(children, Pattern)
+                    .map(Pat::from_node)
+                    .unwrap_or(Pat::Other { span }),
+                exp: find(children, N::Exp)
+                    .map(Exp::from_node)
+                    .unwrap_or(Exp::Other { span }),
+                else_: find(children, ExpNest).map(|n| Box::new(Exp::from_node(n))),
+                span,
+            };
+        }
+        if kind == DecKind::Type {
+            return Dec::Type {
+                id: find_ident(children, node),
+                binds: find(children, TypeBindList)
+                    .map(TypBind::list_from_node)
+                    .unwrap_or_default(),
+                typ: find(children, Type)
+                    .map(Typ::from_node)
+                    .unwrap_or(Typ::Other { span }),
+                span,
+            };
+        }
+
+        let mut shared = None;
+        let mut parenthetical = None;
+        let persistent = has(KeywordPersistent);
+        let mut sort = None;
+        let mut id = None;
+        let mut binds = vec![];
+        let mut param = None;
+        let mut result = None;
+        let mut body = None;
+        let mut this = None;
+        let mut fields = vec![];
+        for n in children.iter() {
+            match n.node_type {
+                SharedPattern | SharedPattern2 => shared = Some(SharedPat::from_node(n)),
+                N::Parenthetical => parenthetical = Some(Parenthetical::from_node(n)),
+                N::ObjSort => sort = obj_sort(n),
+                Id => id = Some(Ident::from_node(n)),
+                TypeBindList => binds = TypBind::list_from_node(n),
+                PatternPlain => param = Some(Pat::from_node(n)),
+                Type => result = Some(Typ::from_node(n)),
+                FuncBody => body = Some(func_body(n)),
+                ObjBody => fields = DecField::list_from_node(n),
+                ClassBody => {
+                    for c in significant(n) {
+                        match c.node_type {
+                            Id => this = Some(Ident::from_node(c)),
+                            ObjBody => fields = DecField::list_from_node(c),
+                            _ => {}
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        let param = param.unwrap_or(Pat::Other { span });
+
+        if kind == DecKind::Class {
+            Dec::Class {
+                shared,
+                parenthetical,
+                persistent,
+                sort,
+                id,
+                binds,
+                param,
+                result,
+                this,
+                fields,
+                span,
+            }
+        } else if kind == DecKind::Func {
+            Dec::Func {
+                shared,
+                id,
+                binds,
+                param,
+                result,
+                body: Box::new(body.unwrap_or(Exp::Other { span })),
+                span,
+            }
+        } else if let Some(sort) = sort {
+            Dec::Obj {
+                parenthetical,
+                persistent,
+                sort,
+                id,
+                fields,
+                span,
+            }
+        } else {
+            Self::other(node)
+        }
+    }
+
+    fn other(node: &Node) -> Self {
+        Dec::Exp {
+            exp: Exp::other(node),
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        match self {
+            Dec::Exp { exp } => exp.span(),
+            Dec::Var { span, .. }
+            | Dec::Let { span, .. }
+            | Dec::Type { span, .. }
+            | Dec::Obj { span, .. }
+            | Dec::Func { span, .. }
+            | Dec::Class { span, .. } => *span,
+        }
+    }
+}
+
+impl SharedPat {
+    fn from_node(node: &Node) -> Self {
+        let children = significant(node);
+        SharedPat {
+            sort: func_sort(node).unwrap_or(FuncSort::Shared),
+            pat: find(&children, PatternPlain).map(Pat::from_node),
+            span: Span::of(node),
+        }
+    }
+}
+
+impl DecField {
+    fn from_node(node: &Node) -> Self {
+        let mut visibility = None;
+        let mut stability = None;
+        let mut dec = None;
+        for n in significant(node) {
+            match n.node_type {
+                N::Visibility => {
+                    visibility = match significant(n).first().map(|k| &k.node_type) {
+                        Some(KeywordPublic) => Some(self::Visibility::Public),
+                        Some(KeywordPrivate) => Some(self::Visibility::Private),
+                        Some(KeywordSystem) => Some(self::Visibility::System),
+                        _ => None,
+                    }
+                }
+                N::Stability => {
+                    stability = match significant(n).first().map(|k| &k.node_type) {
+                        Some(KeywordStable) => Some(self::Stability::Stable),
+                        Some(KeywordFlexible) => Some(self::Stability::Flexible),
+                        Some(KeywordTransient) => Some(self::Stability::Transient),
+                        _ => None,
+                    }
+                }
+                Declaration => dec = Some(Dec::from_node(n)),
+                _ => {}
+            }
+        }
+        DecField {
+            visibility,
+            stability,
+            dec: dec.unwrap_or_else(|| Dec::other(node)),
+            span: Span::of(node),
+        }
+    }
+
+    fn list_from_node(node: &Node) -> Vec<Self> {
+        significant(node)
+            .into_iter()
+            .filter(|n| n.node_type == DeclarationField)
+            .map(Self::from_node)
+            .collect()
+    }
+}
+
+impl Exp {
+    pub(crate) fn from_node(node: &Node) -> Self {
+        let children = significant(node);
+        match node.node_type {
+            N::Exp | ExpNonVar | ExpNest | ExpPlain | Declaration => match children.first() {
+                Some(n) => Self::from_node(n),
+                None => Self::other(node),
+            },
+            DeclarationVar | DeclarationNonVar => Exp::Dec {
+                dec: Box::new(Dec::from_node(node)),
+            },
+            ParenthesizedExp => Exp::Paren {
+                exp: Box::new(first_exp(&children, node)),
+                span: Span::of(node),
+            },
+            TupleExp => Exp::Tuple {
+                exps: children.iter().map(|n| Self::from_node(n)).collect(),
+                span: Span::of(node),
+            },
+            ExpList => {
+                let mut exps: Vec<_> = children.iter().map(|n| Self::from_node(n)).collect();
+                if exps.len() == 1 {
+                    Exp::Paren {
+                        exp: Box::new(exps.remove(0)),
+                        span: Span::of(node),
+                    }
+                } else {
+                    Exp::Tuple {
+                        exps,
+                        span: Span::of(node),
+                    }
+                }
+            }
+            Block => Exp::Block {
+                decs: children
+                    .iter()
+                    .filter(|n| n.node_type == Declaration)
+                    .map(|n| Dec::from_node(n))
+                    .collect(),
+                span: Span::of(node),
+            },
+            ExpObj => {
+                let bases = find(&children, ExpObjBases)
+                    .map(|n| {
+                        significant(n)
+                            .into_iter()
+                            .filter(|n| n.node_type == ExpPost)
+                            .map(Self::from_node)
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                let fields = find(&children, ExpObjFields)
+                    .map(significant)
+                    .unwrap_or_else(|| children.clone());
+                Exp::Obj {
+                    bases,
+                    fields: fields
+                        .iter()
+                        .filter(|n| n.node_type == N::ExpField)
+                        .map(|n| ExpField::from_node(n))
+                        .collect(),
+                    span: Span::of(node),
+                }
+            }
+            ExpNullary => match children.first() {
+                Some(n) if n.node_type == Id => Exp::Var {
+                    id: Ident::from_node(n),
+                },
+                Some(n) => Self::from_node(n),
+                None => Self::other(node),
+            },
+            N::Lit | KeywordTrue | KeywordFalse | KeywordNull => Exp::Lit {
+                lit: Lit::from_node(node),
+            },
+            Id => Exp::Var {
+                id: Ident::from_node(node),
+            },
+            KeywordUnderscore => Exp::Placeholder {
+                span: Span::of(node),
+            },
+            ExpNonDec => Self::from_non_dec(node, &children),
+            ExpBin => Self::from_bin(node, &children),
+            ExpUn => Self::from_un(node, &children),
+            ExpPost => Self::from_post(node, &children),
+            ExpPostFirst => Self::from_post_first(node, &children),
+            VarExpNonVar => match children.last() {
+                Some(n) => Self::from_node(n),
+                None => Self::other(node),
+            },
+            _ => Self::other(node),
+        }
+    }
+
+    fn from_non_dec(node: &Node, children: &[&Node]) -> Self {
+        let span = Span::of(node);
+        let first = match children.first() {
+            Some(n) => n,
+            None => return Self::other(node),
+        };
+        let kind = ExpKind::of(node);
+        if kind == ExpKind::Binary {
+            let lhs = Self::from_node(first);
+            return match (children.get(1), children.get(2)) {
+                (Some(op), Some(rhs)) => Exp::Assign {
+                    lhs: Box::new(lhs),
+                    op: op.original.trim().to_string(),
+                    rhs: Box::new(Self::from_node(rhs)),
+                    span,
+                },
+                _ => lhs,
+            };
+        }
+
+        let nested: Vec<Exp> = children
+            .iter()
+            .filter(|n| matches!(n.node_type, ExpNest | N::Exp | ExpNullary | Block))
+            .map(|n| Self::from_node(n))
+            .collect();
+        let mut nested = nested.into_iter();
+        let mut next = || Box::new(nested.next().unwrap_or(Exp::Other { span }));
+
+        match kind {
+            ExpKind::Assert => Exp::Assert { exp: next(), span },
+            ExpKind::Async => Exp::Async {
+                star: first.node_type == KeywordAsyncStar,
+                exp: next(),
+                span,
+            },
+            ExpKind::Await => Exp::Await {
+                star: first.node_type == KeywordAwaitStar,
+                exp: next(),
+                span,
+            },
+            ExpKind::Debug => Exp::Debug { exp: next(), span },
+            ExpKind::Ignore => Exp::Ignore { exp: next(), span },
+            ExpKind::Throw => Exp::Throw { exp: next(), span },
+            ExpKind::Break => Exp::Break {
+                id: find_ident(children, node),
+                exp: find(children, ExpNullary).map(|n| Box::new(Self::from_node(n))),
+                span,
+            },
+            ExpKind::Continue => Exp::Continue {
+                id: find_ident(children, node),
+                span,
+            },
+            ExpKind::Do => Exp::Do {
+                optional: children.iter().any(|n| n.node_type == Questionmark),
+                block: next(),
+                span,
+            },
+            ExpKind::For => Exp::For {
+                pat: find(children, Pattern)
+                    .map(Pat::from_node)
+                    .unwrap_or(Pat::Other { span }),
+                iter: next(),
+                body: next(),
+                span,
+            },
+            ExpKind::If => {
+                let cond = next();
+                let then = next();
+                let else_ = nested.next().map(Box::new);
+                Exp::If {
+                    cond,
+                    then,
+                    else_,
+                    span,
+                }
+            }
+            ExpKind::Label => Exp::Label {
+                id: find_ident(children, node),
+                typ: find(children, Type).map(Typ::from_node),
+                body: next(),
+                span,
+            },
+            ExpKind::Loop => {
+                let body = next();
+                let cond = nested.next().map(Box::new);
+                Exp::Loop { body, cond, span }
+            }
+            ExpKind::Return => Exp::Return {
+                exp: nested.next().map(Box::new),
+                span,
+            },
+            ExpKind::Switch => Exp::Switch {
+                exp: next(),
+                cases: children
+                    .iter()
+                    .filter(|n| n.node_type == N::Case)
+                    .map(|n| Case::from_node(n))
+                    .collect(),
+                span,
+            },
+            ExpKind::Try => Exp::Try {
+                exp: next(),
+                catch: find(children, N::Catch).map(|n| Box::new(Catch::from_node(n))),
+                finally: find(children, Finally).map(|n| Box::new(first_exp(&significant(n), n))),
+                span,
+            },
+            ExpKind::While => {
+                let cond = next();
+                let body = next();
+                Exp::While { cond, body, span }
+            }
+            ExpKind::Binary | ExpKind::Other => Self::other(node),
+        }
+    }
+
+    fn from_bin(node: &Node, children: &[&Node]) -> Self {
+        if children.is_empty() {
+            return Self::other(node);
+        }
+        let mut operands = vec![];
+        let mut ops = vec![];
+        Self::collect_bin(children, &mut operands, &mut ops);
+        Self::group_bin(operands, ops)
+    }
+
+    /// Operands and operators of the right nested `ExpBin` nodes of a chain, in order
+    fn collect_bin(children: &[&Node], operands: &mut Vec<Exp>, ops: &mut Vec<(String, u8)>) {
+        let Some(first) = children.first() else {
+            return;
+        };
+        operands.push(Self::from_node(first));
+        if let Some(cont) = children.get(1) {
+            Self::collect_bin_continue(cont, operands, ops);
+        }
+    }
+
+    /// Add an `ExpBinContinue` to the chain, a type annotation or a trailing argument
+    /// applies to all of the chain before it
+    fn collect_bin_continue(node: &Node, operands: &mut Vec<Exp>, ops: &mut Vec<(String, u8)>) {
+        let children = significant(node);
+        match children.first().map(|n| &n.node_type) {
+            Some(Colon) => {
+                let exp = Self::group_bin(std::mem::take(operands), std::mem::take(ops));
+                let span = Span {
+                    start: exp.span().start,
+                    end: children.get(1).map_or(node.end, |n| content_end(n)),
+                };
+                operands.push(Exp::Annot {
+                    exp: Box::new(exp),
+                    typ: children
+                        .get(1)
+                        .map(|n| Typ::from_node(n))
+                        .unwrap_or(Typ::Other { span }),
+                    span,
+                });
+                if let Some(n) = children.get(2).filter(|n| n.node_type == ExpBinContinue) {
+                    Self::collect_bin_continue(n, operands, ops);
+                }
+            }
+            Some(KeywordAnd | KeywordOr | BinOp | RelOp) => {
+                let op = children[0].original.trim().to_string();
+                ops.push((op.clone(), precedence(&op).unwrap_or(0)));
+                match children.get(1) {
+                    Some(n) => Self::collect_bin(&significant(n), operands, ops),
+                    None => operands.push(Exp::Other {
+                        span: Span::of(node),
+                    }),
+                }
+            }
+            _ => {}
+        }
+        // trailing type arguments and argument, see `ExpBinContinue` in the grammar
+        if let Some(arg) = children.iter().skip(2).find(|n| n.node_type == N::Exp) {
+            let func = Self::group_bin(std::mem::take(operands), std::mem::take(ops));
+            let span = Span {
+                start: func.span().start,
+                end: content_end(node),
+            };
+            operands.push(Exp::Call {
+                func: Box::new(func),
+                system: false,
+                type_args: find(&children, TypeArgs)
+                    .map(Typ::list_from_node)
+                    .unwrap_or_default(),
+                arg: Box::new(Self::from_node(arg)),
+                span,
+            });
+        }
+    }
+
+    /// Group a chain of binary operators by their precedence, like the generator does
+    fn group_bin(operands: Vec<Exp>, ops: Vec<(String, u8)>) -> Self {
+        group_by_precedence(operands, ops, &|lhs: Exp, op, _, rhs: Exp| {
+            let span = Span {
+                start: lhs.span().start,
+                end: rhs.span().end,
+            };
+            Exp::Binary {
+                lhs: Box::new(lhs),
+                op,
+                rhs: Box::new(rhs),
+                span,
+            }
+        })
+    }
+
+    fn from_un(node: &Node, children: &[&Node]) -> Self {
+        let span = Span::of(node);
+        let first = match children.first() {
+            Some(n) => n,
+            None => return Self::other(node),
+        };
+        let operand = || {
+            Box::new(
+                children
+                    .last()
+                    .map(|n| Self::from_node(n))
+                    .unwrap_or(Exp::Other { span }),
+            )
+        };
+        match first.node_type {
+            Questionmark => Exp::Opt {
+                exp: operand(),
+                span,
+            },
+            KeywordActor => Exp::Actor {
+                exp: operand(),
+                span,
+            },
+            KeywordNot => Exp::Not {
+                exp: operand(),
+                span,
+            },
+            KeywordDebugShow => Exp::DebugShow {
+                exp: operand(),
+                span,
+            },
+            KeywordFromCandid => Exp::FromCandid {
+                exp: operand(),
+                span,
+            },
+            KeywordToCandid => Exp::ToCandid {
+                exps: children
+                    .last()
+                    .map(|n| significant(n).into_iter().map(Self::from_node).collect())
+                    .unwrap_or_default(),
+                span,
+            },
+            HashTag => Exp::Variant {
+                tag: find_ident(children, node),
+                exp: find(children, ExpNullary).map(|n| Box::new(Self::from_node(n))),
+                span,
+            },
+            UnOp | UnAssign => Exp::Unary {
+                op: first.original.trim().to_string(),
+                exp: operand(),
+                span,
+            },
+            RoundBracketOpen => Exp::Paren {
+                exp: Box::new(
+                    find(children, ExpUn)
+                        .map(Self::from_node)
+                        .unwrap_or(Exp::Other { span }),
+                ),
+                span,
+            },
+            _ => Self::from_node(first),
+        }
+    }
+
+    fn from_post(node: &Node, children: &[&Node]) -> Self {
+        match children.first() {
+            Some(first) => Self::apply_post(Self::from_node(first), first.start, &children[1..]),
+            None => Self::other(node),
+        }
+    }
+
+    /// Apply a sequence of `ExpPostContinue` nodes to `exp`
+    fn apply_post(mut exp: Exp, start: usize, conts: &[&Node]) -> Self {
+        for cont in conts {
+            let parts = significant(cont);
+            let span = Span {
+                start,
+                end: content_end(cont),
+            };
+            let boxed = Box::new(exp);
+            exp = match parts.iter().map(|n| &n.node_type).collect::<Vec<_>>()[..] {
+                // the grammar parses `f(x).y` as `f` followed by the nested post expression `(x).y`
+                [ExpPost] => {
+                    let inner = significant(parts[0]);
+                    match inner.first() {
+                        Some(arg) => {
+                            let applied = Self::apply_post_first(*boxed, start, arg);
+                            Self::apply_post(applied, start, &inner[1..])
+                        }
+                        None => Exp::Other { span },
+                    }
+                }
+                [ExpNullary] => Exp::Call {
+                    func: boxed,
+                    system: false,
+                    type_args: vec![],
+                    arg: Box::new(Self::from_node(parts[0])),
+                    span,
+                },
+                [ExpPostList, ExpNullary] => Exp::Call {
+                    func: boxed,
+                    system: parts[0].has_child(&KeywordSystem),
+                    type_args: Typ::list_from_node(parts[0]),
+                    arg: Box::new(Self::from_node(parts[1])),
+                    span,
+                },
+                [SquareBracketOpen, N::Exp, SquareBracketClose] => Exp::Index {
+                    exp: boxed,
+                    index: Box::new(Self::from_node(parts[1])),
+                    span,
+                },
+                [Dot, Nat] => Exp::Proj {
+                    exp: boxed,
+                    index: parts[1].original.trim().to_string(),
+                    span,
+                },
+                [Dot, Id] => Exp::Dot {
+                    exp: boxed,
+                    field: Ident::from_node(parts[1]),
+                    span,
+                },
+                [BANG] => Exp::Bang { exp: boxed, span },
+                _ => Exp::Other { span },
+            };
+        }
+        exp
+    }
+
+    /// Apply an `ExpPostFirst` that follows another expression: `a[1]` or `f(x)`
+    fn apply_post_first(exp: Exp, start: usize, node: &Node) -> Self {
+        let children = significant(node);
+        let span = Span {
+            start,
+            end: content_end(node),
+        };
+        match children.iter().map(|n| &n.node_type).collect::<Vec<_>>()[..] {
+            [SquareBracketOpen, VarExpNonVar, SquareBracketClose]
+                if !significant(children[1])
+                    .iter()
+                    .any(|n| n.node_type == KeywordVar) =>
+            {
+                Exp::Index {
+                    exp: Box::new(exp),
+                    index: Box::new(Self::from_node(children[1])),
+                    span,
+                }
+            }
+            _ => Exp::Call {
+                func: Box::new(exp),
+                system: false,
+                type_args: vec![],
+                arg: Box::new(Self::from_post_first(node, &children)),
+                span,
+            },
+        }
+    }
+
+    fn from_post_first(node: &Node, children: &[&Node]) -> Self {
+        let span = Span::of(node);
+        match children.first().map(|n| &n.node_type) {
+            Some(N::Parenthetical) => Exp::Parenthetical {
+                parenthetical: Parenthetical::from_node(children[0]),
+                exp: Box::new(
+                    find(children, ExpPost)
+                        .map(Self::from_node)
+                        .unwrap_or(Exp::Other { span }),
+                ),
+                span,
+            },
+            Some(RoundBracketOpen) => Exp::Paren {
+                exp: Box::new(first_exp(children, node)),
+                span,
+            },
+            Some(SquareBracketOpen) => {
+                let items: Vec<&&Node> = children
+                    .iter()
+                    .filter(|n| matches!(n.node_type, VarExpNonVar | ExpNonVar))
+                    .collect();
+                let mutable = children.iter().any(|n| n.node_type == KeywordVar)
+                    || items
+                        .first()
+                        .map(|n| significant(n).iter().any(|c| c.node_type == KeywordVar))
+                        .unwrap_or(false);
+                Exp::Array {
+                    mutable,
+                    exps: items.into_iter().map(|n| Self::from_node(n)).collect(),
+                    span,
+                }
+            }
+            Some(_) => Self::from_node(children[0]),
+            None => Self::other(node),
+        }
+    }
+
+    fn other(node: &Node) -> Self {
+        Exp::Other {
+            span: Span::of(node),
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        match self {
+            Exp::Lit { lit } => lit.span,
+            Exp::Var { id } => id.span,
+            Exp::Dec { dec } => dec.span(),
+            Exp::Block { span, .. }
+            | Exp::Obj { span, .. }
+            | Exp::Paren { span, .. }
+            | Exp::Tuple { span, .. }
+            | Exp::Array { span, .. }
+            | Exp::Call { span, .. }
+            | Exp::Dot { span, .. }
+            | Exp::Proj { span, .. }
+            | Exp::Index { span, .. }
+            | Exp::Bang { span, .. }
+            | Exp::Variant { span, .. }
+            | Exp::Opt { span, .. }
+            | Exp::Unary { span, .. }
+            | Exp::Not { span, .. }
+            | Exp::DebugShow { span, .. }
+            | Exp::ToCandid { span, .. }
+            | Exp::FromCandid { span, .. }
+            | Exp::Actor { span, .. }
+            | Exp::Binary { span, .. }
+            | Exp::Annot { span, .. }
+            | Exp::Assign { span, .. }
+            | Exp::If { span, .. }
+            | Exp::Switch { span, .. }
+            | Exp::While { span, .. }
+            | Exp::Loop { span, .. }
+            | Exp::For { span, .. }
+            | Exp::Label { span, .. }
+            | Exp::Break { span, .. }
+            | Exp::Continue { span, .. }
+            | Exp::Return { span, .. }
+            | Exp::Async { span, .. }
+            | Exp::Await { span, .. }
+            | Exp::Assert { span, .. }
+            | Exp::Debug { span, .. }
+            | Exp::Ignore { span, .. }
+            | Exp::Throw { span, .. }
+            | Exp::Try { span, .. }
+            | Exp::Do { span, .. }
+            | Exp::Parenthetical { span, .. }
+            | Exp::Placeholder { span }
+            | Exp::Other { span } => *span,
+        }
+    }
+}
+
+impl ExpField {
+    fn from_node(node: &Node) -> Self {
+        let children = significant(node);
+        ExpField {
+            mutable: children.iter().any(|n| n.node_type == KeywordVar),
+            id: find_ident(&children, node),
+            typ: find(&children, Type).map(Typ::from_node),
+            exp: find(&children, N::Exp).map(Exp::from_node),
+            span: Span::of(node),
+        }
+    }
+}
+
+impl Parenthetical {
+    fn from_node(node: &Node) -> Self {
+        let children = significant(node);
+        Parenthetical {
+            base: find(&children, ExpPost).map(|n| Box::new(Exp::from_node(n))),
+            fields: find(&children, ExpObjFields)
+                .map(|n| {
+                    significant(n)
+                        .into_iter()
+                        .filter(|n| n.node_type == N::ExpField)
+                        .map(ExpField::from_node)
+                        .collect()
+                })
+                .unwrap_or_default(),
+            span: Span::of(node),
+        }
+    }
+}
+
+impl Case {
+    fn from_node(node: &Node) -> Self {
+        let children = significant(node);
+        Case {
+            pat: find(&children, PatternNullary)
+                .map(Pat::from_node)
+                .unwrap_or_else(|| Pat::other(node)),
+            exp: find(&children, ExpNest)
+                .map(Exp::from_node)
+                .unwrap_or_else(|| Exp::other(node)),
+            span: Span::of(node),
+        }
+    }
+}
+
+impl Catch {
+    fn from_node(node: &Node) -> Self {
+        let case = Case::from_node(node);
+        Catch {
+            pat: case.pat,
+            exp: case.exp,
+            span: case.span,
+        }
+    }
+}
+
+impl Pat {
+    pub(crate) fn from_node(node: &Node) -> Self {
+        let children = significant(node);
+        let span = Span::of(node);
+        match node.node_type {
+            Pattern => match children.first() {
+                Some(n) => Self::from_node(n),
+                None => Self::other(node),
+            },
+            PatternBin => {
+                if children.first().map(|n| &n.node_type) == Some(&RoundBracketOpen) {
+                    return Pat::Paren {
+                        pat: Box::new(
+                            find(&children, PatternBin)
+                                .map(Self::from_node)
+                                .unwrap_or(Pat::Other { span }),
+                        ),
+                        span,
+                    };
+                }
+                let mut iter = children.iter();
+                let mut pat = match iter.next() {
+                    Some(n) => Self::from_node(n),
+                    None => return Self::other(node),
+                };
+                while let Some(n) = iter.next() {
+                    let rhs = iter.next();
+                    match (&n.node_type, rhs) {
+                        (KeywordOr, Some(rhs)) => {
+                            pat = Pat::Or {
+                                lhs: Box::new(pat),
+                                rhs: Box::new(Self::from_node(rhs)),
+                                span: Span::between(node, rhs),
+                            }
+                        }
+                        (Colon, Some(typ)) => {
+                            pat = Pat::Annot {
+                                pat: Box::new(pat),
+                                typ: Typ::from_node(typ),
+                                span: Span::between(node, typ),
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                pat
+            }
+            PatternUn => match children.first().map(|n| &n.node_type) {
+                Some(HashTag) => Pat::Variant {
+                    tag: find_ident(&children, node),
+                    pat: find(&children, PatternNullary).map(|n| Box::new(Self::from_node(n))),
+                    span,
+                },
+                Some(Questionmark) => Pat::Opt {
+                    pat: Box::new(
+                        children
+                            .last()
+                            .map(|n| Self::from_node(n))
+                            .unwrap_or(Pat::Other { span }),
+                    ),
+                    span,
+                },
+                Some(UnOp) => Pat::Signed {
+                    op: children[0].original.trim().to_string(),
+                    lit: children
+                        .last()
+                        .map(|n| Lit::from_node(n))
+                        .unwrap_or_else(|| Lit::from_node(node)),
+                    span,
+                },
+                Some(_) => Self::from_node(children[0]),
+                None => Self::other(node),
+            },
+            PatternNullary => {
+                if node.is_first_child(&CurlyBracketOpen) {
+                    Pat::Obj {
+                        fields: children
+                            .iter()
+                            .filter(|n| n.node_type == PatternField)
+                            .map(|n| PatField::from_node(n))
+                            .collect(),
+                        span,
+                    }
+                } else {
+                    match children.first() {
+                        Some(n) => Self::from_node(n),
+                        None => Self::other(node),
+                    }
+                }
+            }
+            PatternPlain => match children.first().map(|n| &n.node_type) {
+                Some(KeywordUnderscore) => Pat::Wild { span },
+                Some(Id) => Pat::Var {
+                    id: Ident::from_node(children[0]),
+                },
+                Some(N::Lit | KeywordTrue | KeywordFalse | KeywordNull) => Pat::Lit {
+                    lit: Lit::from_node(children[0]),
+                },
+                Some(RoundBracketOpen) => {
+                    let mut pats: Vec<Pat> = children
+                        .iter()
+                        .filter(|n| n.node_type == PatternBin)
+                        .map(|n| Self::from_node(n))
+                        .collect();
+                    if pats.len() == 1 {
+                        Pat::Paren {
+                            pat: Box::new(pats.remove(0)),
+                            span,
+                        }
+                    } else {
+                        Pat::Tuple { pats, span }
+                    }
+                }
+                _ => Self::other(node),
+            },
+            _ => Self::other(node),
+        }
+    }
+
+    fn other(node: &Node) -> Self {
+        Pat::Other {
+            span: Span::of(node),
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        match self {
+            Pat::Var { id } => id.span,
+            Pat::Lit { lit } => lit.span,
+            Pat::Wild { span }
+            | Pat::Paren { span, .. }
+            | Pat::Tuple { span, .. }
+            | Pat::Obj { span, .. }
+            | Pat::Variant { span, .. }
+            | Pat::Opt { span, .. }
+            | Pat::Signed { span, .. }
+            | Pat::Or { span, .. }
+            | Pat::Annot { span, .. }
+            | Pat::Other { span } => *span,
+        }
+    }
+}
+
+impl PatField {
+    fn from_node(node: &Node) -> Self {
+        let children = significant(node);
+        PatField {
+            id: find_ident(&children, node),
+            typ: find(&children, Type).map(Typ::from_node),
+            pat: find(&children, Pattern).map(Pat::from_node),
+            span: Span::of(node),
+        }
+    }
+}
+
+impl Typ {
+    pub(crate) fn from_node(node: &Node) -> Self {
+        let children = significant(node);
+        let span = Span::of(node);
+        let first = children.first().map(|n| &n.node_type);
+        match node.node_type {
+            Type => match first {
+                // tuple type: `"(" Type,* ")"`, the brackets are not part of the tree
+                Some(Type) | None => Typ::Tuple {
+                    items: children
+                        .iter()
+                        .map(|n| TypItem {
+                            id: None,
+                            typ: Self::from_node(n),
+                            span: Span::of(n),
+                        })
+                        .collect(),
+                    span,
+                },
+                Some(KeywordNullType | KeywordNoneType | KeywordAnyType | KeywordErrorType)
+                | Some(KeywordActor) => Typ::Prim {
+                    name: children[0].original.trim().to_string(),
+                    span,
+                },
+                Some(_) => {
+                    let lhs = Self::from_node(children[0]);
+                    match (children.get(1), children.get(2)) {
+                        (Some(op), Some(rhs)) if op.node_type == KeywordAnd => Typ::And {
+                            lhs: Box::new(lhs),
+                            rhs: Box::new(Self::from_node(rhs)),
+                            span,
+                        },
+                        (Some(_), Some(rhs)) => Typ::Or {
+                            lhs: Box::new(lhs),
+                            rhs: Box::new(Self::from_node(rhs)),
+                            span,
+                        },
+                        _ => lhs,
+                    }
+                }
+            },
+            TypeNoBin => {
+                if let Some(arrow) = children.iter().position(|n| n.node_type == Arrow) {
+                    let arg = children[..arrow]
+                        .iter()
+                        .find(|n| n.node_type == TypeUn)
+                        .map(|n| Self::from_node(n))
+                        .unwrap_or(Typ::Other { span });
+                    Typ::Func {
+                        sort: find(&children, N::FuncSort).and_then(func_sort),
+                        binds: find(&children, TypeBindList)
+                            .map(TypBind::list_from_node)
+                            .unwrap_or_default(),
+                        arg: Box::new(arg),
+                        result: Box::new(
+                            children
+                                .get(arrow + 1)
+                                .map(|n| Self::from_node(n))
+                                .unwrap_or(Typ::Other { span }),
+                        ),
+                        span,
+                    }
+                } else {
+                    match children.first() {
+                        Some(n) => Self::from_node(n),
+                        None => Self::other(node),
+                    }
+                }
+            }
+            TypePre => match first {
+                Some(KeywordAsync) | Some(KeywordAsyncStar) => Typ::Async {
+                    star: first == Some(&KeywordAsyncStar),
+                    typ: Box::new(
+                        children
+                            .last()
+                            .map(|n| Self::from_node(n))
+                            .unwrap_or(Typ::Other { span }),
+                    ),
+                    span,
+                },
+                Some(N::ObjSort) => {
+                    let fields = find(&children, TypeObj)
+                        .map(TypField::list_from_node)
+                        .unwrap_or_default();
+                    Typ::Obj {
+                        sort: obj_sort(children[0]),
+                        fields,
+                        span,
+                    }
+                }
+                Some(_) => Self::from_node(children[0]),
+                None => Self::other(node),
+            },
+            TypeUn => match first {
+                Some(Questionmark) => Typ::Opt {
+                    typ: Box::new(
+                        children
+                            .last()
+                            .map(|n| Self::from_node(n))
+                            .unwrap_or(Typ::Other { span }),
+                    ),
+                    span,
+                },
+                Some(_) => Self::from_node(children[0]),
+                None => Self::other(node),
+            },
+            TypeNullary => match first {
+                Some(RoundBracketOpen) => {
+                    let mut items: Vec<TypItem> = children
+                        .iter()
+                        .filter(|n| n.node_type == TypeItem)
+                        .map(|n| TypItem::from_node(n))
+                        .collect();
+                    if items.len() == 1 && items[0].id.is_none() {
+                        Typ::Paren {
+                            typ: Box::new(items.remove(0).typ),
+                            span,
+                        }
+                    } else {
+                        Typ::Tuple { items, span }
+                    }
+                }
+                Some(SquareBracketOpen) => Typ::Array {
+                    mutable: children.iter().any(|n| n.node_type == KeywordVar),
+                    elem: Box::new(
+                        find(&children, Type)
+                            .map(Self::from_node)
+                            .unwrap_or(Typ::Other { span }),
+                    ),
+                    span,
+                },
+                Some(TypeVariant | TypeObj) => Self::from_node(children[0]),
+                Some(Id) => Typ::Path {
+                    path: children
+                        .iter()
+                        .filter(|n| n.node_type == Id)
+                        .map(|n| Ident::from_node(n))
+                        .collect(),
+                    args: find(&children, TypeArgs)
+                        .map(Self::list_from_node)
+                        .unwrap_or_default(),
+                    span,
+                },
+                _ => Self::other(node),
+            },
+            TypeVariant => Typ::Variant {
+                tags: children
+                    .iter()
+                    .filter(|n| n.node_type == TypeTag)
+                    .map(|n| TypTag::from_node(n))
+                    .collect(),
+                span,
+            },
+            TypeObj => Typ::Obj {
+                sort: None,
+                fields: TypField::list_from_node(node),
+                span,
+            },
+            _ => Self::other(node),
+        }
+    }
+
+    /// Types of a `TypeArgs` or `ExpPostList` node
+    fn list_from_node(node: &Node) -> Vec<Self> {
+        significant(node)
+            .into_iter()
+            .filter(|n| n.node_type == Type)
+            .map(Self::from_node)
+            .collect()
+    }
+
+    fn other(node: &Node) -> Self {
+        Typ::Other {
+            span: Span::of(node),
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        match self {
+            Typ::Path { span, .. }
+            | Typ::Prim { span, .. }
+            | Typ::Paren { span, .. }
+            | Typ::Tuple { span, .. }
+            | Typ::Array { span, .. }
+            | Typ::Opt { span, .. }
+            | Typ::Variant { span, .. }
+            | Typ::Obj { span, .. }
+            | Typ::Func { span, .. }
+            | Typ::Async { span, .. }
+            | Typ::And { span, .. }
+            | Typ::Or { span, .. }
+            | Typ::Other { span } => *span,
+        }
+    }
+}
+
+impl TypItem {
+    fn from_node(node: &Node) -> Self {
+        let children = significant(node);
+        TypItem {
+            id: find(&children, Id).map(Ident::from_node),
+            typ: find(&children, Type)
+                .map(Typ::from_node)
+                .unwrap_or_else(|| Typ::other(node)),
+            span: Span::of(node),
+        }
+    }
+}
+
+impl TypTag {
+    fn from_node(node: &Node) -> Self {
+        let children = significant(node);
+        TypTag {
+            tag: find_ident(&children, node),
+            typ: find(&children, Type).map(Typ::from_node),
+            span: Span::of(node),
+        }
+    }
+}
+
+impl TypField {
+    fn from_node(node: &Node) -> Self {
+        let children = significant(node);
+        TypField {
+            mutable: children.iter().any(|n| n.node_type == KeywordVar),
+            id: find_ident(&children, node),
+            binds: find(&children, TypeBindList)
+                .map(TypBind::list_from_node)
+                .unwrap_or_default(),
+            param: find(&children, TypeNullary).map(Typ::from_node),
+            typ: find(&children, Type)
+                .map(Typ::from_node)
+                .unwrap_or_else(|| Typ::other(node)),
+            span: Span::of(node),
+        }
+    }
+
+    fn list_from_node(node: &Node) -> Vec<Self> {
+        significant(node)
+            .into_iter()
+            .filter(|n| n.node_type == TypeField)
+            .map(Self::from_node)
+            .collect()
+    }
+}
+
+impl TypBind {
+    fn from_node(node: &Node) -> Self {
+        let children = significant(node);
+        TypBind {
+            id: find(&children, KeywordSystem)
+                .map(Ident::from_node)
+                .unwrap_or_else(|| find_ident(&children, node)),
+            bound: find(&children, Type).map(Typ::from_node),
+            span: Span::of(node),
+        }
+    }
+
+    fn list_from_node(node: &Node) -> Vec<Self> {
+        significant(node)
+            .into_iter()
+            .filter(|n| n.node_type == TypeBind)
+            .map(Self::from_node)
+            .collect()
+    }
+}
+
+/// Children without whitespace, comments and separators
+fn significant(node: &Node) -> Vec<&Node> {
+    node.children
+        .iter()
+        .filter(|n| {
+            !matches!(
+                n.node_type,
+                WHITESPACE | COMMENT | Comment | Semicolon | EOI
+            )
+        })
+        .collect()
+}
+
+/// End of the node without trailing whitespace and comments
+fn content_end(node: &Node) -> usize {
+    match significant(node).last() {
+        Some(last) => content_end(last),
+        None => node.end,
+    }
+}
+
+fn find<'a>(nodes: &[&'a Node], node_type: NodeType) -> Option<&'a Node> {
+    nodes.iter().find(|n| n.node_type == node_type).copied()
+}
+
+/// First `Id` in `nodes`, or an empty identifier located at `parent`
+fn find_ident(nodes: &[&Node], parent: &Node) -> Ident {
+    find(nodes, Id).map(Ident::from_node).unwrap_or(Ident {
+        name: String::new(),
+        span: Span {
+            start: parent.start,
+            end: parent.start,
+        },
+    })
+}
+
+fn first_exp(nodes: &[&Node], parent: &Node) -> Exp {
+    nodes
+        .iter()
+        .find(|n| matches!(n.node_type, N::Exp | ExpPost | ExpUn))
+        .map(|n| Exp::from_node(n))
+        .unwrap_or_else(|| Exp::other(parent))
+}
+
+fn func_body(node: &Node) -> Exp {
+    match significant(node).first() {
+        Some(n) => Exp::from_node(n),
+        None => Exp::other(node),
+    }
+}
+
+fn obj_sort(node: &Node) -> Option<ObjSort> {
+    match significant(node).first().map(|n| &n.node_type) {
+        Some(KeywordObject) => Some(ObjSort::Object),
+        Some(KeywordActor) => Some(ObjSort::Actor),
+        Some(KeywordModule) => Some(ObjSort::Module),
+        _ => None,
+    }
+}
+
+fn func_sort(node: &Node) -> Option<FuncSort> {
+    let children = significant(node);
+    let has = |t: NodeType| children.iter().any(|n| n.node_type == t);
+    match (has(KeywordShared), has(KeywordComposite), has(KeywordQuery)) {
+        (true, true, true) => Some(FuncSort::SharedCompositeQuery),
+        (true, false, true) => Some(FuncSort::SharedQuery),
+        (true, _, false) => Some(FuncSort::Shared),
+        (false, true, true) => Some(FuncSort::CompositeQuery),
+        (false, false, true) => Some(FuncSort::Query),
+        (false, _, false) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_decs(content: &str) -> Vec<Dec> {
+        match parse(content) {
+            Ok(program) => program.decs,
+            Err(err) => panic!("Couldn't parse:\n{}", err),
+        }
+    }
+
+    #[test]
+    fn test_imports() {
+        let program =
+            parse("import Array \"mo:base/Array\";\nimport { map } = \"mo:base/Iter\";").unwrap();
+        assert_eq!(program.imports.len(), 2);
+        assert_eq!(program.imports[0].url, "mo:base/Array");
+        assert!(matches!(&program.imports[0].pat, Pat::Var { id } if id.name == "Array"));
+        assert!(matches!(&program.imports[1].pat, Pat::Obj { fields, .. } if fields.len() == 1));
+    }
+
+    #[test]
+    fn test_let_and_var() {
+        let decs = parse_decs("let x : Nat = 1; var y = \"a\";");
+        assert_eq!(decs.len(), 2);
+        match &decs[0] {
+            Dec::Let { pat, exp, span, .. } => {
+                assert!(matches!(pat, Pat::Annot { .. }));
+                assert!(matches!(exp, Exp::Lit { lit } if lit.kind == LitKind::Nat));
+                assert_eq!(*span, Span { start: 0, end: 15 });
+            }
+            dec => panic!("expected let, got {:?}", dec),
+        }
+        assert!(matches!(&decs[1], Dec::Var { id, .. } if id.name == "y"));
+    }
+
+    #[test]
+    fn test_func() {
+        let decs = parse_decs("actor A { public shared query func get<T>(x : T) : async T { x } }");
+        let fields = match &decs[0] {
+            Dec::Obj {
+                sort: ObjSort::Actor,
+                id: Some(id),
+                fields,
+                ..
+            } if id.name == "A" => fields,
+            dec => panic!("expected actor, got {:?}", dec),
+        };
+        assert_eq!(fields[0].visibility, Some(Visibility::Public));
+        match &fields[0].dec {
+            Dec::Func {
+                shared: Some(shared),
+                id: Some(id),
+                binds,
+                result: Some(Typ::Async { .. }),
+                body,
+                ..
+            } => {
+                assert_eq!(shared.sort, FuncSort::SharedQuery);
+                assert_eq!(id.name, "get");
+                assert_eq!(binds.len(), 1);
+                assert!(matches!(**body, Exp::Block { ref decs, .. } if decs.len() == 1));
+            }
+            dec => panic!("expected func, got {:?}", dec),
+        }
+    }
+
+    #[test]
+    fn test_expressions() {
+        let decs = parse_decs("a.b(1)[2].0; x := y + 1; switch (v) { case (#a) 1; case _ 2 };");
+        match &decs[0] {
+            Dec::Exp {
+                exp: Exp::Proj { exp, index, .. },
+            } => {
+                assert_eq!(index, "0");
+                assert!(matches!(**exp, Exp::Index { .. }));
+            }
+            dec => panic!("expected projection, got {:?}", dec),
+        }
+        match &decs[1] {
+            Dec::Exp {
+                exp: Exp::Assign { op, rhs, .. },
+            } => {
+                assert_eq!(op, ":=");
+                assert!(matches!(**rhs, Exp::Binary { ref op, .. } if op == "+"));
+            }
+            dec => panic!("expected assignment, got {:?}", dec),
+        }
+        match &decs[2] {
+            Dec::Exp {
+                exp: Exp::Switch { cases, .. },
+            } => {
+                assert_eq!(cases.len(), 2);
+                assert!(matches!(cases[1].pat, Pat::Wild { .. }));
+            }
+            dec => panic!("expected switch, got {:?}", dec),
+        }
+    }
+
+    #[test]
+    fn test_types() {
+        let decs =
+            parse_decs("type T<A> = { #leaf; #node : (T<A>, ?A) } and shared Nat -> async ();");
+        match &decs[0] {
+            Dec::Type { id, binds, typ, .. } => {
+                assert_eq!(id.name, "T");
+                assert_eq!(binds.len(), 1);
+                match typ {
+                    Typ::And { lhs, rhs, .. } => {
+                        assert!(matches!(**lhs, Typ::Variant { ref tags, .. } if tags.len() == 2));
+                        assert!(matches!(
+                            **rhs,
+                            Typ::Func {
+                                sort: Some(FuncSort::Shared),
+                                ..
+                            }
+                        ));
+                    }
+                    typ => panic!("expected and type, got {:?}", typ),
+                }
+            }
+            dec => panic!("expected type declaration, got {:?}", dec),
+        }
+    }
+
+    #[test]
+    fn test_modern_syntax() {
+        let decs = parse_decs(
+            "persistent actor { transient let c = 0; public composite query func q() : async* Nat { 1 } }; \
+             try { await* f() } catch e { 1 } finally { 2 }; try { x } finally { y }",
+        );
+        let fields = match &decs[0] {
+            Dec::Obj {
+                persistent: true,
+                sort: ObjSort::Actor,
+                fields,
+                ..
+            } => fields,
+            dec => panic!("expected persistent actor, got {:?}", dec),
+        };
+        assert_eq!(fields[0].stability, Some(Stability::Transient));
+        match &fields[1].dec {
+            Dec::Func {
+                shared: Some(shared),
+                result: Some(Typ::Async { star: true, .. }),
+                ..
+            } => assert_eq!(shared.sort, FuncSort::CompositeQuery),
+            dec => panic!("expected func, got {:?}", dec),
+        }
+        match &decs[1] {
+            Dec::Exp {
+                exp:
+                    Exp::Try {
+                        exp,
+                        catch: Some(_),
+                        finally: Some(_),
+                        ..
+                    },
+            } => assert!(matches!(
+                **exp,
+                Exp::Block { ref decs, .. }
+                    if matches!(decs[0], Dec::Exp { exp: Exp::Await { star: true, .. } })
+            )),
+            dec => panic!("expected try, got {:?}", dec),
+        }
+        assert!(matches!(
+            &decs[2],
+            Dec::Exp {
+                exp: Exp::Try {
+                    catch: None,
+                    finally: Some(_),
+                    ..
+                }
+            }
+        ));
+    }
+
+    #[test]
+    fn test_record_extension() {
+        let decs = parse_decs("{ a and b.c with x = 1; var y = 2 }; { a and b }");
+        match &decs[0] {
+            Dec::Exp {
+                exp: Exp::Obj { bases, fields, .. },
+            } => {
+                assert!(matches!(&bases[0], Exp::Var { id } if id.name == "a"));
+                assert!(matches!(&bases[1], Exp::Dot { .. }));
+                assert_eq!(fields.len(), 2);
+                assert!(fields[1].mutable);
+            }
+            dec => panic!("expected object, got {:?}", dec),
+        }
+        assert!(matches!(
+            &decs[1],
+            Dec::Exp {
+                exp: Exp::Obj { bases, fields, .. }
+            } if bases.len() == 2 && fields.is_empty()
+        ));
+    }
+
+    #[test]
+    fn test_let_else() {
+        let decs = parse_decs("let ?x = o else { return }; let y = 1;");
+        match &decs[0] {
+            Dec::Let {
+                pat: Pat::Opt { .. },
+                else_: Some(else_),
+                ..
+            } => assert!(matches!(**else_, Exp::Block { .. })),
+            dec => panic!("expected let-else, got {:?}", dec),
+        }
+        assert!(matches!(&decs[1], Dec::Let { else_: None, .. }));
+    }
+
+    #[test]
+    fn test_pipe() {
+        let decs = parse_decs("x |> f _ |> g(_, 1)");
+        match &decs[0] {
+            Dec::Exp {
+                exp: Exp::Binary { op, lhs, rhs, .. },
+            } => {
+                assert_eq!(op, "|>");
+                assert!(matches!(**rhs, Exp::Call { .. }));
+                match &**lhs {
+                    Exp::Binary { rhs, .. } => assert!(matches!(
+                        **rhs,
+                        Exp::Call { ref arg, .. } if matches!(**arg, Exp::Placeholder { .. })
+                    )),
+                    exp => panic!("expected pipe, got {:?}", exp),
+                }
+            }
+            dec => panic!("expected pipe, got {:?}", dec),
+        }
+    }
+
+    /// Binary expressions with parentheses around each operator, other expressions as written
+    fn grouped(content: &str) -> String {
+        fn group(exp: &Exp, content: &str) -> String {
+            match exp {
+                Exp::Binary { lhs, op, rhs, .. } => {
+                    format!("({} {} {})", group(lhs, content), op, group(rhs, content))
+                }
+                exp => content[exp.span().start..exp.span().end].trim().to_string(),
+            }
+        }
+        match &parse_decs(content)[0] {
+            Dec::Exp { exp } => group(exp, content),
+            dec => panic!("expected expression, got {:?}", dec),
+        }
+    }
+
+    #[test]
+    fn test_binary_precedence() {
+        assert_eq!(grouped("1 - 2 * 3 - 4"), "((1 - (2 * 3)) - 4)");
+        assert_eq!(grouped("1 - 2 - 3"), "((1 - 2) - 3)");
+        assert_eq!(
+            grouped("a or b and c == d + 1"),
+            "(a or (b and (c == (d + 1))))"
+        );
+        assert_eq!(
+            grouped("x << 2 + 1 * 3 ** 2"),
+            "((x << 2) + (1 * (3 ** 2)))"
+        );
+        assert_eq!(grouped("a # b # c |> f _"), "(((a # b) # c) |> f _)");
+    }
+
+    #[test]
+    fn test_kinds() {
+        fn kinds(node: &Node, decs: &mut Vec<DecKind>, exps: &mut Vec<ExpKind>) {
+            match node.node_type {
+                DeclarationNonVar => decs.push(DecKind::of(node)),
+                ExpNonDec => exps.push(ExpKind::of(node)),
+                _ => {}
+            }
+            for child in node.children.iter() {
+                kinds(child, decs, exps);
+            }
+        }
+        let nodes = super::super::parse(
+            "let a = 1; type T = Nat; func f() {}; x := 1; if (a) { return } else { loop {} };",
+        )
+        .unwrap();
+        let (mut decs, mut exps) = (vec![], vec![]);
+        kinds(&nodes[0], &mut decs, &mut exps);
+        assert_eq!(decs, vec![DecKind::Let, DecKind::Type, DecKind::Func]);
+        // all other expressions, e.g. `1` and `x := 1`
+        exps.retain(|kind| *kind != ExpKind::Binary);
+        assert_eq!(exps, vec![ExpKind::If, ExpKind::Return, ExpKind::Loop]);
+    }
+
+    #[test]
+    fn test_parenthetical() {
+        let decs = parse_decs(
+            "(with migration = m) persistent actor { }; (with cycles = 1; timeout = 5) a.f()",
+        );
+        match &decs[0] {
+            Dec::Obj {
+                parenthetical: Some(parenthetical),
+                persistent: true,
+                ..
+            } => {
+                assert!(parenthetical.base.is_none());
+                assert_eq!(parenthetical.fields[0].id.name, "migration");
+            }
+            dec => panic!("expected actor, got {:?}", dec),
+        }
+        match &decs[1] {
+            Dec::Exp {
+                exp:
+                    Exp::Parenthetical {
+                        parenthetical, exp, ..
+                    },
+            } => {
+                assert_eq!(parenthetical.fields.len(), 2);
+                assert!(matches!(**exp, Exp::Call { .. }));
+            }
+            dec => panic!("expected call with parenthetical, got {:?}", dec),
+        }
+    }
+
+    #[test]
+    fn test_system_type_args() {
+        let decs = parse_decs("func f<system, T>(x : T) {}; Timer.setTimer<system>(d, f); a<b");
+        match &decs[0] {
+            Dec::Func { binds, .. } => {
+                let names: Vec<_> = binds.iter().map(|b| b.id.name.as_str()).collect();
+                assert_eq!(names, vec!["system", "T"]);
+            }
+            dec => panic!("expected function, got {:?}", dec),
+        }
+        assert!(matches!(
+            &decs[1],
+            Dec::Exp {
+                exp: Exp::Call {
+                    system: true,
+                    ref type_args,
+                    ..
+                }
+            } if type_args.is_empty()
+        ));
+        assert!(matches!(
+            &decs[2],
+            Dec::Exp {
+                exp: Exp::Binary { ref op, .. }
+            } if op == "<"
+        ));
+    }
+}
@@ -0,0 +1,370 @@
+//! Comments are collected by the grammar wherever whitespace is allowed, so they often end
+//! up inside the node before them, e.g. between a declaration and its `;`. After parsing,
+//! each comment is moved into the list it belongs to and attached to the code around it.
+
+use super::parser::{Node, NodeType};
+
+/// How a comment relates to the code around it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Attachment {
+    /// Before the following node, on its own line or followed by code: `// one\nlet a = 1`
+    Leading,
+    /// After a node or separator on the same line: `let a = 1; // one`
+    Trailing,
+    /// Inside brackets or a list without any items: `{ /* empty */ }`
+    Dangling,
+}
+
+/// Move the comments of `node` out of the items they were parsed into and set their attachment
+pub fn attach_comments(node: &mut Node, text: &str) {
+    move_end_of_file_comments(node, text);
+    attach(node, text);
+    if let Some(header) = node
+        .children
+        .iter_mut()
+        .find(|n| n.node_type == NodeType::Header)
+    {
+        for comment in header.children.iter_mut() {
+            if comment.node_type == NodeType::Comment {
+                set_attachment(comment, Attachment::Leading);
+            }
+        }
+    }
+}
+
+/// Comments after the last declaration are parsed between the program and the end of input,
+/// they belong to the last list with items
+fn move_end_of_file_comments(node: &mut Node, text: &str) {
+    let Some(program) = node
+        .children
+        .iter()
+        .position(|n| n.node_type == NodeType::Program)
+    else {
+        return;
+    };
+    let end = node
+        .children
+        .iter()
+        .position(|n| n.node_type == NodeType::EOI)
+        .unwrap_or(node.children.len());
+    if !node.children[program + 1..end].iter().any(is_comment) {
+        return;
+    }
+    let comments: Vec<Node> = node.children.drain(program + 1..end).collect();
+
+    let program = &mut node.children[program];
+    let list = program
+        .children
+        .iter()
+        .rposition(|n| is_list(n) && has_items(n));
+    match list {
+        Some(i) => {
+            program.children[i].children.extend(comments);
+            update_span(&mut program.children[i], text);
+        }
+        None => program.children.extend(comments),
+    }
+    update_span(program, text);
+}
+
+fn attach(node: &mut Node, text: &str) {
+    for child in node.children.iter_mut() {
+        attach(child, text);
+    }
+    if is_list(node) {
+        lift_trailing_comments(node, text);
+    }
+
+    let dangling = is_list(node) && !has_items(node);
+    for i in 0..node.children.len() {
+        if node.children[i].node_type != NodeType::COMMENT {
+            continue;
+        }
+        let comment = &node.children[i];
+        // a separator moved before the comment is still after it in the text
+        let previous = node.children[..i]
+            .iter()
+            .rev()
+            .find(|n| !is_trivia(n) && n.start < n.end && n.end <= comment.start);
+        let attachment = match previous {
+            _ if dangling => Attachment::Dangling,
+            Some(p) if !is_open_bracket(p) && !text[p.end..comment.start].contains('\n') => {
+                Attachment::Trailing
+            }
+            _ => Attachment::Leading,
+        };
+        set_attachment(&mut node.children[i], attachment);
+    }
+}
+
+/// Set the attachment of a group of comments and of each comment in it.
+/// The comments after the first one are trailing while they're on the same line.
+fn set_attachment(node: &mut Node, attachment: Attachment) {
+    node.attachment = Some(attachment);
+    for child in node.children.iter_mut() {
+        match child.node_type {
+            NodeType::Comment => set_attachment(child, attachment),
+            NodeType::SpacedComment => {
+                let newline = child
+                    .children
+                    .iter()
+                    .any(|n| n.node_type == NodeType::WHITESPACE && n.original.contains('\n'));
+                let next = match attachment {
+                    Attachment::Trailing if newline => Attachment::Leading,
+                    _ => attachment,
+                };
+                set_attachment(child, next);
+            }
+            NodeType::DocComment | NodeType::LineComment | NodeType::BlockComment => {
+                child.attachment = Some(attachment)
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Comments at the end of an item become siblings of it, so that a separator can be placed
+/// between them, e.g. `b = 2 // two` is split into `b = 2` and `// two`.
+/// Comments before a separator on the same line stay in place: `(1 /* one */, 2)`.
+fn lift_trailing_comments(node: &mut Node, text: &str) {
+    let mut i = 0;
+    while i < node.children.len() {
+        let item = &node.children[i];
+        if !is_item(item) || is_list(item) || !ends_with_comment(item) {
+            i += 1;
+            continue;
+        }
+        let next = (i + 1..node.children.len()).find(|j| !is_trivia(&node.children[*j]));
+        let next_start = next.map_or(node.end, |j| node.children[j].start);
+        let separated = next.is_some_and(|j| {
+            let n = &node.children[j];
+            is_item(n) || n.node_type == NodeType::Semicolon
+        });
+        if separated && !text[code_end(item)..next_start].contains('\n') {
+            i += 1;
+            continue;
+        }
+
+        let mut trailing = vec![];
+        take_trailing(&mut node.children[i], text, &mut trailing);
+        let count = trailing.len();
+        node.children.splice(i + 1..i + 1, trailing);
+        // the separator goes directly after the item, before its comments
+        if let Some(j) = next.map(|j| j + count) {
+            if node.children[j].node_type == NodeType::Semicolon {
+                let separator = node.children.remove(j);
+                node.children.insert(i + 1, separator);
+            }
+        }
+        i += 1;
+    }
+}
+
+fn take_trailing(node: &mut Node, text: &str, trailing: &mut Vec<Node>) {
+    if node.children.is_empty() {
+        return;
+    }
+    while node.children.last().is_some_and(is_trivia) {
+        trailing.insert(0, node.children.pop().unwrap());
+    }
+    if let Some(last) = node.children.last_mut() {
+        if !is_list(last) {
+            take_trailing(last, text, trailing);
+        }
+    }
+    update_span(node, text);
+}
+
+fn ends_with_comment(node: &Node) -> bool {
+    let code = node.children.iter().rposition(|n| !is_trivia(n));
+    node.children[code.map_or(0, |i| i + 1)..]
+        .iter()
+        .any(is_comment)
+        || code.is_some_and(|i| !is_list(&node.children[i]) && ends_with_comment(&node.children[i]))
+}
+
+/// End of `node` without the whitespace and comments at its end
+fn code_end(node: &Node) -> usize {
+    match node.children.iter().rposition(|n| !is_trivia(n)) {
+        Some(i) if !is_list(&node.children[i]) => code_end(&node.children[i]),
+        Some(i) => node.children[i].end,
+        None if node.children.is_empty() => node.end,
+        None => node.start,
+    }
+}
+
+/// Fit the span of `node` to its children after some of them have been moved
+fn update_span(node: &mut Node, text: &str) {
+    node.end = node.children.last().map_or(node.start, |n| n.end);
+    node.original = text[node.start..node.end].replace("\t", "  ");
+}
+
+/// Nodes whose children are items separated by `;` or `,`, maybe surrounded by brackets
+fn is_list(node: &Node) -> bool {
+    matches!(
+        node.node_type,
+        NodeType::DeclarationList
+            | NodeType::ImportList
+            | NodeType::ExpObjFields
+            | NodeType::ExpList
+            | NodeType::TypeArgs
+            | NodeType::TypeVariant
+    ) || node.children.first().is_some_and(is_open_bracket)
+}
+
+fn has_items(node: &Node) -> bool {
+    node.children.iter().any(is_item)
+}
+
+fn is_item(node: &Node) -> bool {
+    !is_trivia(node)
+        && !is_open_bracket(node)
+        && !matches!(
+            node.node_type,
+            NodeType::Semicolon
+                | NodeType::RoundBracketClose
+                | NodeType::CurlyBracketClose
+                | NodeType::SquareBracketClose
+                | NodeType::AngleBracketClose
+        )
+        && node.start < node.end
+}
+
+fn is_open_bracket(node: &Node) -> bool {
+    matches!(
+        node.node_type,
+        NodeType::RoundBracketOpen
+            | NodeType::CurlyBracketOpen
+            | NodeType::SquareBracketOpen
+            | NodeType::AngleBracketOpen
+    )
+}
+
+fn is_comment(node: &Node) -> bool {
+    node.node_type == NodeType::COMMENT
+}
+
+fn is_trivia(node: &Node) -> bool {
+    matches!(node.node_type, NodeType::COMMENT | NodeType::WHITESPACE)
+}
+
+#[cfg(test)]
+mod test_attachment {
+    use super::*;
+    use crate::motoko_parser::parse;
+
+    /// Comments with their attachment, the type of their parent and the sibling before them
+    fn comments(text: &str) -> Vec<(String, Attachment, NodeType, Option<NodeType>)> {
+        fn collect(
+            node: &Node,
+            result: &mut Vec<(String, Attachment, NodeType, Option<NodeType>)>,
+        ) {
+            for (i, child) in node.children.iter().enumerate() {
+                if let (NodeType::COMMENT, Some(attachment)) = (&child.node_type, child.attachment)
+                {
+                    let previous = node.children[..i]
+                        .iter()
+                        .rev()
+                        .find(|n| n.node_type != NodeType::WHITESPACE)
+                        .map(|n| n.node_type.clone());
+                    let parent = node.node_type.clone();
+                    result.push((child.original.clone(), attachment, parent, previous));
+                }
+                collect(child, result);
+            }
+        }
+        let mut result = vec![];
+        collect(&parse(text).unwrap()[0], &mut result);
+        result
+    }
+
+    #[test]
+    fn test_leading_and_trailing() {
+        let comments = comments("let z = 0;\n// one\nlet a = 1; // two\nlet b = 2 /* three */;");
+        let attachments: Vec<(&str, Attachment)> = comments
+            .iter()
+            .map(|(text, attachment, ..)| (text.as_str(), *attachment))
+            .collect();
+        assert_eq!(
+            attachments,
+            vec![
+                ("// one", Attachment::Leading),
+                ("// two", Attachment::Trailing),
+                ("/* three */", Attachment::Trailing),
+            ]
+        );
+        // the comment before the separator on the same line stays in the declaration
+        assert_eq!(comments[2].3, Some(NodeType::ExpPostFirst));
+    }
+
+    #[test]
+    fn test_comments_in_group() {
+        fn collect(node: &Node, result: &mut Vec<(String, Option<Attachment>)>) {
+            if matches!(
+                node.node_type,
+                NodeType::LineComment | NodeType::BlockComment
+            ) {
+                result.push((node.original.clone(), node.attachment));
+            }
+            for child in node.children.iter() {
+                collect(child, result);
+            }
+        }
+        let mut result = vec![];
+        let text = "// zero\nlet a = 1; /* one */ // two\n// three\nlet b = 2;";
+        collect(&parse(text).unwrap()[0], &mut result);
+        assert_eq!(
+            result,
+            vec![
+                ("// zero".to_string(), Some(Attachment::Leading)),
+                ("/* one */".to_string(), Some(Attachment::Trailing)),
+                ("// two".to_string(), Some(Attachment::Trailing)),
+                ("// three".to_string(), Some(Attachment::Leading)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_separator_before_comment() {
+        let comments = comments("let o = { a = 1 // one\n  ; b = 2 };");
+        assert_eq!(
+            comments,
+            vec![(
+                "// one".to_string(),
+                Attachment::Trailing,
+                NodeType::ExpObj,
+                Some(NodeType::Semicolon)
+            )]
+        );
+    }
+
+    #[test]
+    fn test_dangling() {
+        let comments = comments("module { /* empty */ };\nf(\n  // none\n);");
+        let attachments: Vec<(&str, Attachment, &NodeType)> = comments
+            .iter()
+            .map(|(text, attachment, parent, _)| (text.as_str(), *attachment, parent))
+            .collect();
+        assert_eq!(
+            attachments,
+            vec![
+                ("/* empty */", Attachment::Dangling, &NodeType::ObjBody),
+                ("// none", Attachment::Dangling, &NodeType::ExpList),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_end_of_file() {
+        let comments = comments("import A \"a\";\nlet a = 1;\n// end\n");
+        assert_eq!(
+            comments,
+            vec![(
+                "// end".to_string(),
+                Attachment::Leading,
+                NodeType::DeclarationList,
+                Some(NodeType::Semicolon)
+            )]
+        );
+    }
+}
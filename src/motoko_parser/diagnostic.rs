@@ -0,0 +1,293 @@
+/// Human readable parse errors
+use core::fmt;
+use pest::error::{Error, ErrorVariant, InputLocation, LineColLocation};
+use std::path::{Path, PathBuf};
+
+use super::parser::Rule;
+
+/// A parse error pointing to a location in the source file
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub file_path: Option<PathBuf>,
+    /// 1-based line number
+    pub line: usize,
+    /// 1-based column number, counted in characters
+    pub column: usize,
+    /// Byte offset of the error in the source text
+    pub offset: usize,
+    /// The source line containing the error
+    pub snippet: String,
+    /// Number of characters to underline, at least one
+    pub width: usize,
+    /// Tokens in Motoko syntax that would have been valid at this position
+    pub expected: Vec<String>,
+    /// Tokens in Motoko syntax that were not allowed at this position
+    pub unexpected: Vec<String>,
+    /// Message for errors that are not about expected tokens
+    pub custom_message: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn from_pest(file_path: Option<&Path>, text: &str, err: &Error<Rule>) -> Self {
+        let (offset, end) = match err.location {
+            InputLocation::Pos(pos) => (pos, pos),
+            InputLocation::Span((start, end)) => (start, end),
+        };
+        let (line, column) = match err.line_col {
+            LineColLocation::Pos(pos) => pos,
+            LineColLocation::Span(start, _) => start,
+        };
+
+        let line_start = text[..offset].rfind('\n').map(|i| i + 1).unwrap_or(0);
+        let line_end = text[offset..]
+            .find('\n')
+            .map(|i| offset + i)
+            .unwrap_or(text.len());
+        let snippet = text[line_start..line_end].trim_end().to_string();
+        let width = text[offset..end.min(line_end)].chars().count().max(1);
+
+        let (expected, unexpected, custom_message) = match &err.variant {
+            ErrorVariant::ParsingError {
+                positives,
+                negatives,
+            } => (surface_tokens(positives), surface_tokens(negatives), None),
+            ErrorVariant::CustomError { message } => (vec![], vec![], Some(message.clone())),
+        };
+
+        Diagnostic {
+            file_path: file_path.map(Path::to_path_buf),
+            line,
+            column,
+            offset,
+            snippet,
+            width,
+            expected,
+            unexpected,
+            custom_message,
+        }
+    }
+
+    /// Short description of the error without location
+    pub fn message(&self) -> String {
+        if let Some(message) = &self.custom_message {
+            return message.clone();
+        }
+        match (self.expected.is_empty(), self.unexpected.is_empty()) {
+            (false, true) => format!("expected {}", join_or(&self.expected)),
+            (true, false) => format!("unexpected {}", join_or(&self.unexpected)),
+            (false, false) => format!(
+                "unexpected {}, expected {}",
+                join_or(&self.unexpected),
+                join_or(&self.expected)
+            ),
+            (true, true) => "syntax error".to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let line_number = self.line.to_string();
+        let pad = " ".repeat(line_number.len());
+        let path = self
+            .file_path
+            .as_ref()
+            .map(|p| p.display().to_string())
+            .unwrap_or_default();
+
+        // tabs would move the caret, so they are shown as single spaces
+        let snippet = self.snippet.replace('\t', " ");
+        let caret_pad = " ".repeat(self.column.saturating_sub(1));
+
+        writeln!(f, "error: {}", self.message())?;
+        writeln!(f, "{}--> {}:{}:{}", pad, path, self.line, self.column)?;
+        writeln!(f, "{} |", pad)?;
+        writeln!(f, "{} | {}", line_number, snippet)?;
+        write!(f, "{} | {}{}", pad, caret_pad, "^".repeat(self.width))
+    }
+}
+
+impl std::error::Error for Diagnostic {}
+
+fn join_or(tokens: &[String]) -> String {
+    match tokens {
+        [] => String::new(),
+        [one] => one.clone(),
+        [init @ .., last] => format!("{} or {}", init.join(", "), last),
+    }
+}
+
+/// Map grammar rules to a deduplicated list of tokens as they are written in Motoko
+fn surface_tokens(rules: &[Rule]) -> Vec<String> {
+    let mut tokens: Vec<String> = vec![];
+    for rule in rules {
+        if let Some(token) = surface_token(rule) {
+            if !tokens.iter().any(|t| t == token) {
+                tokens.push(token.to_string());
+            }
+        }
+    }
+    tokens
+}
+
+fn surface_token(rule: &Rule) -> Option<&'static str> {
+    use Rule::*;
+    let token = match rule {
+        Semicolon => "`;`",
+        EqualSign => "`=`",
+        Colon => "`:`",
+        Dot => "`.`",
+        RoundBracketOpen => "`(`",
+        RoundBracketClose => "`)`",
+        CurlyBracketOpen => "`{`",
+        CurlyBracketClose => "`}`",
+        SquareBracketOpen => "`[`",
+        SquareBracketClose => "`]`",
+        AngleBracketOpen => "`<`",
+        AngleBracketClose => "`>`",
+        Questionmark => "`?`",
+        HashTag => "`#`",
+        Arrow => "`->`",
+        ColonEqual => "`:=`",
+        BANG => "`!`",
+
+        KeywordActor => "`actor`",
+        KeywordAnd => "`and`",
+        KeywordAssert => "`assert`",
+        KeywordAsync => "`async`",
+        KeywordAsyncStar => "`async*`",
+        KeywordAwait => "`await`",
+        KeywordAwaitStar => "`await*`",
+        KeywordBreak => "`break`",
+        KeywordCase => "`case`",
+        KeywordCatch => "`catch`",
+        KeywordClass => "`class`",
+        KeywordComposite => "`composite`",
+        KeywordContinue => "`continue`",
+        KeywordDebug => "`debug`",
+        KeywordDebugShow => "`debug_show`",
+        KeywordDo => "`do`",
+        KeywordElse => "`else`",
+        KeywordFinally => "`finally`",
+        KeywordFlexible => "`flexible`",
+        KeywordFalse => "`false`",
+        KeywordFor => "`for`",
+        KeywordFromCandid => "`from_candid`",
+        KeywordFunc => "`func`",
+        KeywordIf => "`if`",
+        KeywordIgnore => "`ignore`",
+        KeywordImport => "`import`",
+        KeywordIn => "`in`",
+        KeywordModule => "`module`",
+        KeywordNot => "`not`",
+        KeywordNull => "`null`",
+        KeywordObject => "`object`",
+        KeywordOr => "`or`",
+        KeywordLabel => "`label`",
+        KeywordLet => "`let`",
+        KeywordLoop => "`loop`",
+        KeywordPersistent => "`persistent`",
+        KeywordPrivate => "`private`",
+        KeywordPublic => "`public`",
+        KeywordQuery => "`query`",
+        KeywordReturn => "`return`",
+        KeywordShared => "`shared`",
+        KeywordStable => "`stable`",
+        KeywordSwitch => "`switch`",
+        KeywordSystem => "`system`",
+        KeywordThrow => "`throw`",
+        KeywordToCandid => "`to_candid`",
+        KeywordTransient => "`transient`",
+        KeywordTrue => "`true`",
+        KeywordTry => "`try`",
+        KeywordType => "`type`",
+        KeywordVar => "`var`",
+        KeywordWhile => "`while`",
+        KeywordWith => "`with`",
+        KeywordNullType => "`Null`",
+        KeywordNoneType => "`None`",
+        KeywordAnyType => "`Any`",
+        KeywordErrorType => "`Error`",
+        KeywordUnderscore => "`_`",
+
+        UnOp => "unary operator",
+        BinOp | RelOp => "binary operator",
+        BinAssign | UnAssign => "assignment operator",
+        Id => "identifier",
+        Lit | Nat | Num | HexNum | Float => "literal",
+        Text => "text literal",
+        Char => "character literal",
+        Import => "import",
+        Declaration | DeclarationVar | DeclarationNonVar | DeclarationField => "declaration",
+        Visibility => "visibility",
+        Stability => "stability",
+        FuncSort | ObjSort => "sort",
+        Block | FuncBody | ObjBody | ClassBody => "block",
+        Case => "`case`",
+        Catch => "`catch`",
+        Finally => "`finally`",
+        Pattern | PatternBin | PatternPlain | PatternUn | PatternNullary | PatternField => {
+            "pattern"
+        }
+        Type | TypeNoBin | TypePre | TypeUn | TypeNullary | TypeItem | TypeObj | TypeVariant
+        | TypeTag | TypeField => "type",
+        TypeArgs | TypeBind | TypeBindList => "type parameters",
+        Exp | ExpNonVar | ExpNonDec | ExpNest | ExpUn | ExpPost | ExpPostFirst
+        | ExpPostContinue | ExpPostList | VarExpNonVar | ExpNullary | ExpObj | ExpObjBases
+        | ExpObjFields | ExpField | ExpList | ExpPlain | ExpBin | ExpBinContinue
+        | ParenthesizedExp | TupleExp => "expression",
+        Parenthetical => "`(with ...)`",
+        EOI => "end of file",
+        _ => return None,
+    };
+    Some(token)
+}
+
+#[cfg(test)]
+mod test_diagnostic {
+    use super::*;
+    use crate::motoko_parser::{invalid_part_errors, parse};
+
+    fn first_diagnostic(content: &str) -> Diagnostic {
+        let err = match parse(content) {
+            Err(err) => err,
+            Ok(nodes) => invalid_part_errors(content, &nodes).remove(0),
+        };
+        Diagnostic::from_pest(Some(Path::new("file.mo")), content, &err)
+    }
+
+    #[test]
+    fn test_location_and_snippet() {
+        let diagnostic = first_diagnostic("let a = 1;\nlet b = = 2;\n");
+        assert_eq!((diagnostic.line, diagnostic.column), (2, 9));
+        assert_eq!(diagnostic.offset, 19);
+        assert_eq!(diagnostic.snippet, "let b = = 2;");
+        assert_eq!(
+            diagnostic.to_string(),
+            [
+                format!("error: {}", diagnostic.message()),
+                " --> file.mo:2:9".to_string(),
+                "  |".to_string(),
+                "2 | let b = = 2;".to_string(),
+                "  |         ^".to_string(),
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn test_expected_tokens() {
+        let diagnostic = first_diagnostic("actor { let x = 1 }}");
+        assert_eq!(diagnostic.expected, vec!["end of file", "`;`"]);
+        assert_eq!(diagnostic.message(), "expected end of file or `;`");
+        assert!(!diagnostic.to_string().contains("Semicolon"));
+    }
+
+    #[test]
+    fn test_join_or() {
+        let tokens = ["`;`".to_string(), "`}`".to_string(), "`)`".to_string()];
+        assert_eq!(join_or(&tokens[..1]), "`;`");
+        assert_eq!(join_or(&tokens[..2]), "`;` or `}`");
+        assert_eq!(join_or(&tokens), "`;`, `}` or `)`");
+    }
+}
@@ -1,2 +1,7 @@
+pub mod ast;
+mod attachment;
+pub mod diagnostic;
 mod parser;
+pub use attachment::Attachment;
+pub use diagnostic::Diagnostic;
 pub use parser::*;
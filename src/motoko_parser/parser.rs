@@ -1,4 +1,5 @@
 /// Parser to generate AST Nodes
+use super::attachment::{attach_comments, Attachment};
 use core::fmt;
 use pest::iterators::Pair;
 use pest::Parser;
@@ -14,6 +15,8 @@ pub struct Node {
     pub end: usize,
     pub children: Vec<Node>,
     pub node_type: NodeType,
+    /// Set for comments, see [`attach_comments`]
+    pub attachment: Option<Attachment>,
 }
 
 macro_rules! make_node_types {
@@ -47,6 +50,7 @@ make_node_types! {
     ImportList,
     Declaration,
     DeclarationList,
+    InvalidPart,
     LineComment,
     DocComment,
     BlockComment,
@@ -65,6 +69,9 @@ make_node_types! {
     Nat,
     Text,
     Num,
+    HexNum,
+    Float,
+    Char,
     ObjSort,
     ObjBody,
     EqualSign,
@@ -110,6 +117,9 @@ make_node_types! {
     ExpPostList,
     ExpUn,
     ExpObj,
+    ExpObjBases,
+    ExpObjFields,
+    Parenthetical,
     ExpField,
     FuncBody,
     BinOp,
@@ -118,9 +128,14 @@ make_node_types! {
     UnOp,
     Case,
     Catch,
+    Finally,
     Stability,
     FuncSort,
     TypeBindList,
+    ParenthesizedExp,
+    TupleExp,
+    UnAssign,
+    BANG,
     ColonEqual,
     Dot,
     Colon,
@@ -140,16 +155,20 @@ make_node_types! {
     KeywordAnd,
     KeywordAssert,
     KeywordAsync,
+    KeywordAsyncStar,
     KeywordAwait,
+    KeywordAwaitStar,
     KeywordBreak,
     KeywordCase,
     KeywordCatch,
     KeywordClass,
+    KeywordComposite,
     KeywordContinue,
     KeywordDebug,
     KeywordDebugShow,
     KeywordDo,
     KeywordElse,
+    KeywordFinally,
     KeywordFlexible,
     KeywordFalse,
     KeywordFor,
@@ -167,6 +186,7 @@ make_node_types! {
     KeywordLabel,
     KeywordLet,
     KeywordLoop,
+    KeywordPersistent,
     KeywordPrivate,
     KeywordPublic,
     KeywordQuery,
@@ -177,27 +197,85 @@ make_node_types! {
     KeywordSystem,
     KeywordThrow,
     KeywordToCandid,
+    KeywordTransient,
     KeywordTrue,
     KeywordTry,
     KeywordType,
     KeywordVar,
     KeywordWhile,
+    KeywordWith,
     KeywordUnderscore,
+    KeywordNullType,
+    KeywordNoneType,
+    KeywordAnyType,
+    KeywordErrorType,
     //
 }
 
-#[derive(Debug, Clone)]
-pub struct Declaration {}
-
 pub fn parse(content: &str) -> std::result::Result<Vec<Node>, pest::error::Error<Rule>> {
     let mut ast = vec![];
     let mut pairs = MotokoParser::parse(Rule::Motoko, &content)?;
     let pair = pairs.next().unwrap();
-    ast.push(Node::from_pair(pair));
+    let mut node = Node::from_pair(pair);
+    attach_comments(&mut node, content);
+    ast.push(node);
 
     Ok(ast)
 }
 
+/// Errors for all parts of the file that have been skipped by the error recovery
+///
+/// Each `InvalidPart` is parsed again on its own to get an error pointing into it.
+pub fn invalid_part_errors(content: &str, nodes: &[Node]) -> Vec<pest::error::Error<Rule>> {
+    let mut errors = vec![];
+    for node in nodes {
+        collect_invalid_part_errors(content, node, Rule::StrictDeclaration, &mut errors);
+    }
+    errors
+}
+
+fn collect_invalid_part_errors(
+    content: &str,
+    node: &Node,
+    rule: Rule,
+    errors: &mut Vec<pest::error::Error<Rule>>,
+) {
+    if node.node_type == NodeType::InvalidPart {
+        errors.push(invalid_part_error(content, node, rule));
+        return;
+    }
+    let rule = match node.node_type {
+        NodeType::ObjBody => Rule::StrictDeclarationField,
+        NodeType::DeclarationList => Rule::StrictDeclaration,
+        _ => rule,
+    };
+    for child in node.children.iter() {
+        collect_invalid_part_errors(content, child, rule, errors);
+    }
+}
+
+fn invalid_part_error(content: &str, node: &Node, rule: Rule) -> pest::error::Error<Rule> {
+    use pest::error::{Error, ErrorVariant, InputLocation};
+    use pest::Position;
+
+    let position = |offset: usize| Position::new(content, node.start + offset).unwrap();
+
+    match MotokoParser::parse(rule, &content[node.start..node.end]) {
+        Err(err) => match err.location {
+            InputLocation::Pos(pos) => Error::new_from_pos(err.variant, position(pos)),
+            InputLocation::Span((start, end)) => {
+                Error::new_from_span(err.variant, position(start).span(&position(end)))
+            }
+        },
+        Ok(_) => Error::new_from_pos(
+            ErrorVariant::CustomError {
+                message: "unexpected declaration".into(),
+            },
+            position(0),
+        ),
+    }
+}
+
 #[cfg(test)]
 fn parse_with(
     content: &str,
@@ -226,6 +304,7 @@ impl Node {
             start,
             end,
             original,
+            attachment: None,
         }
     }
 
@@ -240,6 +319,7 @@ impl Node {
             .collect()
     }
 
+    #[cfg(test)]
     fn get_one_descendant(&self, node_type: &NodeType) -> Option<Node> {
         if self.node_type == *node_type {
             return Some(self.clone());
@@ -261,12 +341,10 @@ impl Node {
         None
     }
 
-    pub fn has_descendant(&self, node_type: &NodeType) -> bool {
-        self.get_one_descendant(node_type).is_some()
-    }
-
     pub fn has_child(&self, node_type: &NodeType) -> bool {
-        self.get_one_child(node_type).is_some()
+        self.children
+            .iter()
+            .any(|child| child.node_type == *node_type)
     }
 
     pub fn starts_with(&self, node_type: &NodeType) -> bool {
@@ -971,5 +1049,62 @@ mod test_parsers {
     #[test]
     fn test_float() {
         expect_parse!("0x644.", Rule::Lit, NodeType::Lit);
+        expect_parse!("1.5e10", Rule::Lit, NodeType::Lit);
+        expect_parse!("1_000.5E-3", Rule::Lit, NodeType::Lit);
+        expect_parse!("0x1.8P3", Rule::Lit, NodeType::Lit);
+        expect_parse!("0xFF", Rule::Lit, NodeType::Nat);
+    }
+
+    #[test]
+    fn test_invalid_part() {
+        expect_parse!(
+            "let a = 1; let b = = 2;",
+            Rule::Motoko,
+            NodeType::InvalidPart
+        );
+        expect_parse!(
+            "actor { func f( { }; let x = 1 }",
+            Rule::Motoko,
+            NodeType::InvalidPart
+        );
+
+        let content = "let a = 1;\nlet b = = 2;\nlet c = 3;";
+        let nodes = parse(content).unwrap();
+        let errors = invalid_part_errors(content, &nodes);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].line_col,
+            pest::error::LineColLocation::Pos((2, 9))
+        );
+
+        let content = "let a = 1;";
+        let nodes = parse(content).unwrap();
+        assert!(invalid_part_errors(content, &nodes).is_empty());
+    }
+
+    #[test]
+    fn test_unicode_id() {
+        expect_parse!("abc_123", Rule::Id, NodeType::Id);
+        expect_parse!("_x", Rule::Id, NodeType::Id);
+        expect_parse!("größe", Rule::Id, NodeType::Id);
+        expect_parse!("café", Rule::Id, NodeType::Id);
+        expect_parse!("λ", Rule::Id, NodeType::Id);
+        expect_parse!("名前", Rule::Id, NodeType::Id);
+        expect_parse!("переменная1", Rule::Id, NodeType::Id);
+        expect_parse!("let größe = 1", Rule::Declaration, NodeType::Id);
+        expect_parse!(
+            "func λ(x : Nat) : Nat { x }",
+            Rule::Declaration,
+            NodeType::Id
+        );
+        expect_parse!("{ größe = 1; 名前 = \"a\" }", Rule::ExpObj, NodeType::Id);
+
+        // keywords followed by unicode characters are identifiers
+        expect_parse!("letß", Rule::Id, NodeType::Id);
+
+        assert!(parse_with("1abc", Rule::Id).is_err());
+        assert!(parse_with("\u{00B7}a", Rule::Id).is_err()); // middle dot is not XID_START
+        assert!(parse_with("😀", Rule::Id).is_err());
+        assert!(parse_with("let", Rule::Id).is_err());
     }
 }
@@ -28,6 +28,16 @@ fn test_declarations() {
     test_specs_in("tests/specs/declarations");
 }
 
+#[test]
+fn test_errors() {
+    test_specs_in("tests/specs/errors");
+}
+
+#[test]
+fn test_ignore() {
+    test_specs_in("tests/specs/ignore");
+}
+
 #[test]
 fn test_whitespaces() {
     test_specs_in("tests/specs/whitespace");